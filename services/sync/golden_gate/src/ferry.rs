@@ -21,6 +21,7 @@ pub enum Ferry {
     SyncFinished,
     Reset,
     Wipe,
+    RecentSyncHistory,
 }
 
 impl Ferry {
@@ -39,6 +40,7 @@ impl Ferry {
             Ferry::SyncFinished => concat!(module_path!(), "syncFinished"),
             Ferry::Reset => concat!(module_path!(), "reset"),
             Ferry::Wipe => concat!(module_path!(), "wipe"),
+            Ferry::RecentSyncHistory => concat!(module_path!(), "getRecentSyncHistory"),
         }
     }
 }
@@ -50,6 +52,12 @@ pub enum FerryResult {
     LastSync(i64),
     SyncId(Option<String>),
     AssignedSyncId(String),
+    /// The JSON summary of the sync session that just finished - see
+    /// `crate::telemetry::SyncSession`.
+    SyncFinished(String),
+    /// The JSON array of recent sync summaries - see
+    /// `crate::telemetry::SyncHistory`.
+    RecentSyncHistory(String),
     Null,
 }
 
@@ -68,6 +76,8 @@ impl FerryResult {
             FerryResult::SyncId(Some(v)) => nsCString::from(v).into_variant(),
             FerryResult::SyncId(None) => ().into_variant(),
             FerryResult::AssignedSyncId(v) => nsCString::from(v).into_variant(),
+            FerryResult::SyncFinished(json) => nsCString::from(json).into_variant(),
+            FerryResult::RecentSyncHistory(json) => nsCString::from(json).into_variant(),
             FerryResult::Null => ().into_variant(),
         }
     }
@@ -107,6 +107,8 @@ pub mod error;
 mod ferry;
 pub mod log;
 pub mod task;
+mod teardown;
+pub mod telemetry;
 
 pub use crate::log::LogSink;
 pub use error::{Error, Result};
@@ -117,3 +119,5 @@ pub use sync15::bso::{IncomingBso, OutgoingBso};
 pub use sync15::engine::{ApplyResults, BridgedEngine};
 pub use sync15::Guid;
 pub use task::{ApplyTask, FerryTask};
+pub use teardown::TeardownCoordinator;
+pub use telemetry::{SyncHistory, SyncSession};
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use std::{fmt::Write, mem, result};
+use std::{fmt::Write, mem, result, sync::Arc, time::Instant};
 
 use atomic_refcell::AtomicRefCell;
 use moz_task::{DispatchOptions, Task, TaskRunnable, ThreadPtrHandle, ThreadPtrHolder};
@@ -20,6 +20,7 @@ use xpcom::{
 
 use crate::error::{Error, Result};
 use crate::ferry::{Ferry, FerryResult};
+use crate::telemetry::{SyncHistory, SyncSession};
 
 /// A ferry task sends (or ferries) an operation to a bridged engine on a
 /// background thread or task queue, and ferries back an optional result to
@@ -41,6 +42,14 @@ pub struct FerryTask {
     ferry: Ferry,
     callback: ThreadPtrHandle<mozIBridgedSyncEngineCallback>,
     result: AtomicRefCell<anyhow::Result<FerryResult>>,
+    /// The sync session this ferry is part of, if its bridge is tracking
+    /// one - see `crate::telemetry::SyncSession`. `None` for ferries that
+    /// happen outside a sync (eg `Reset`, `Wipe`, `EnsureCurrentSyncId`).
+    session: Option<Arc<SyncSession>>,
+    /// The bridge's recent-sync history, if it's tracking one - see
+    /// `crate::telemetry::SyncHistory`. Only set for `Ferry::SyncFinished`
+    /// (to record into it) and `Ferry::RecentSyncHistory` (to read it back).
+    history: Option<Arc<SyncHistory>>,
 }
 
 impl FerryTask {
@@ -50,7 +59,7 @@ impl FerryTask {
         engine: Box<dyn BridgedEngine>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
-        Self::with_ferry(engine, Ferry::LastSync, callback)
+        Self::with_ferry(engine, Ferry::LastSync, None, None, callback)
     }
 
     /// Creates a task to set the engine's last sync time, in milliseconds.
@@ -60,7 +69,13 @@ impl FerryTask {
         last_sync_millis: i64,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
-        Self::with_ferry(engine, Ferry::SetLastSync(last_sync_millis), callback)
+        Self::with_ferry(
+            engine,
+            Ferry::SetLastSync(last_sync_millis),
+            None,
+            None,
+            callback,
+        )
     }
 
     /// Creates a task to fetch the engine's sync ID.
@@ -69,7 +84,7 @@ impl FerryTask {
         engine: Box<dyn BridgedEngine>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
-        Self::with_ferry(engine, Ferry::SyncId, callback)
+        Self::with_ferry(engine, Ferry::SyncId, None, None, callback)
     }
 
     /// Creates a task to reset the engine's sync ID and all its local Sync
@@ -79,7 +94,7 @@ impl FerryTask {
         engine: Box<dyn BridgedEngine>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
-        Self::with_ferry(engine, Ferry::ResetSyncId, callback)
+        Self::with_ferry(engine, Ferry::ResetSyncId, None, None, callback)
     }
 
     /// Creates a task to compare the bridged engine's local sync ID with
@@ -94,28 +109,36 @@ impl FerryTask {
         Self::with_ferry(
             engine,
             Ferry::EnsureCurrentSyncId(std::str::from_utf8(new_sync_id)?.into()),
+            None,
+            None,
             callback,
         )
     }
 
-    /// Creates a task to signal that the engine is about to sync.
+    /// Creates a task to signal that the engine is about to sync. `session`
+    /// accumulates what happens across the ferries that are given it, up to
+    /// the matching `for_sync_finished` - see `crate::telemetry::SyncSession`.
     #[inline]
     pub fn for_sync_started(
         engine: Box<dyn BridgedEngine>,
+        session: Arc<SyncSession>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
-        Self::with_ferry(engine, Ferry::SyncStarted, callback)
+        Self::with_ferry(engine, Ferry::SyncStarted, Some(session), None, callback)
     }
 
     /// Creates a task to store incoming records.
     pub fn for_store_incoming(
         engine: Box<dyn BridgedEngine>,
         incoming_envelopes_json: &[nsCString],
+        session: Arc<SyncSession>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
         Self::with_ferry(
             engine,
             Ferry::StoreIncoming(incoming_envelopes_json.to_vec()),
+            Some(session),
+            None,
             callback,
         )
     }
@@ -127,25 +150,38 @@ impl FerryTask {
         engine: Box<dyn BridgedEngine>,
         server_modified_millis: i64,
         uploaded_ids: &[nsCString],
+        session: Arc<SyncSession>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
         let uploaded_ids = uploaded_ids.iter().map(|id| Guid::from_slice(id)).collect();
         Self::with_ferry(
             engine,
             Ferry::SetUploaded(server_modified_millis, uploaded_ids),
+            Some(session),
+            None,
             callback,
         )
     }
 
     /// Creates a task to signal that all records have been uploaded, and
     /// the engine has been synced. This is called even if there were no
-    /// records uploaded.
+    /// records uploaded. Its `FerryResult::SyncFinished` carries the JSON
+    /// summary accumulated in `session` over the course of the sync, which
+    /// is also recorded into `history` - see `crate::telemetry::SyncHistory`.
     #[inline]
     pub fn for_sync_finished(
         engine: Box<dyn BridgedEngine>,
+        session: Arc<SyncSession>,
+        history: Arc<SyncHistory>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
-        Self::with_ferry(engine, Ferry::SyncFinished, callback)
+        Self::with_ferry(
+            engine,
+            Ferry::SyncFinished,
+            Some(session),
+            Some(history),
+            callback,
+        )
     }
 
     /// Creates a task to reset all local Sync state for the engine, without
@@ -155,7 +191,7 @@ impl FerryTask {
         engine: Box<dyn BridgedEngine>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
-        Self::with_ferry(engine, Ferry::Reset, callback)
+        Self::with_ferry(engine, Ferry::Reset, None, None, callback)
     }
 
     /// Creates a task to erase all local user data for the engine.
@@ -164,7 +200,24 @@ impl FerryTask {
         engine: Box<dyn BridgedEngine>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
-        Self::with_ferry(engine, Ferry::Wipe, callback)
+        Self::with_ferry(engine, Ferry::Wipe, None, None, callback)
+    }
+
+    /// Creates a task to fetch the bridge's recently-finished sync summaries
+    /// - see `crate::telemetry::SyncHistory`.
+    #[inline]
+    pub fn for_recent_sync_history(
+        engine: Box<dyn BridgedEngine>,
+        history: Arc<SyncHistory>,
+        callback: &mozIBridgedSyncEngineCallback,
+    ) -> Result<FerryTask> {
+        Self::with_ferry(
+            engine,
+            Ferry::RecentSyncHistory,
+            None,
+            Some(history),
+            callback,
+        )
     }
 
     /// Creates a task for a ferry. The `callback` is bound to the current
@@ -173,6 +226,8 @@ impl FerryTask {
     fn with_ferry(
         engine: Box<dyn BridgedEngine>,
         ferry: Ferry,
+        session: Option<Arc<SyncSession>>,
+        history: Option<Arc<SyncHistory>>,
         callback: &mozIBridgedSyncEngineCallback,
     ) -> Result<FerryTask> {
         let name = ferry.name();
@@ -184,6 +239,8 @@ impl FerryTask {
                 RefPtr::new(callback),
             )?,
             result: AtomicRefCell::new(Err(Error::DidNotRun(name).into())),
+            session,
+            history,
         })
     }
 
@@ -200,6 +257,25 @@ impl FerryTask {
         Ok(())
     }
 
+    /// Dispatches the task straight to the background thread pool, instead
+    /// of a bridge's own serial queue - for read-only ferries (eg
+    /// `Ferry::LastSync`) that don't need to wait behind heavy writes like
+    /// `Ferry::StoreIncoming` that happen to already be queued up. Unlike
+    /// `dispatch`, this only changes how long the ferry waits to *start* -
+    /// `golden_gate` itself does nothing to serialize the read against a
+    /// write that's already running on another thread, so a caller's
+    /// `BridgedEngine` needs its own internal locking for this to be safe
+    /// (eg `webext_storage_bridge` relies on `ThreadSafeStorageDb`'s mutex in
+    /// `webext-storage`'s `StorageDb` for this). Don't use this for an engine
+    /// whose `BridgedEngine` impl isn't already safe to call concurrently
+    /// with itself.
+    pub fn dispatch_background(self) -> Result<()> {
+        let runnable = TaskRunnable::new(self.ferry.name(), Box::new(self))?;
+        runnable
+            .dispatch_background_task_with_options(DispatchOptions::default().may_block(true))?;
+        Ok(())
+    }
+
     /// Runs the task on the background thread. This is split out into its own
     /// method to make error handling easier.
     fn inner_run(&self) -> anyhow::Result<FerryResult> {
@@ -226,15 +302,30 @@ impl FerryTask {
                     .collect::<Result<_>>()?;
 
                 engine.store_incoming(incoming_envelopes)?;
+                if let Some(session) = &self.session {
+                    session.record_incoming_applied(incoming_envelopes_json.len() as u64);
+                }
                 FerryResult::default()
             }
             Ferry::SetUploaded(server_modified_millis, uploaded_ids) => {
                 engine.set_uploaded(*server_modified_millis, uploaded_ids.as_slice())?;
+                if let Some(session) = &self.session {
+                    session.record_outgoing_uploaded(uploaded_ids.len() as u64);
+                }
                 FerryResult::default()
             }
             Ferry::SyncFinished => {
                 engine.sync_finished()?;
-                FerryResult::default()
+                match &self.session {
+                    Some(session) => {
+                        let summary = session.to_json();
+                        if let Some(history) = &self.history {
+                            history.record(summary.clone());
+                        }
+                        FerryResult::SyncFinished(summary.to_string())
+                    }
+                    None => FerryResult::default(),
+                }
             }
             Ferry::Reset => {
                 engine.reset()?;
@@ -244,13 +335,27 @@ impl FerryTask {
                 engine.wipe()?;
                 FerryResult::default()
             }
+            Ferry::RecentSyncHistory => FerryResult::RecentSyncHistory(
+                self.history
+                    .as_ref()
+                    .map(|history| history.to_json().to_string())
+                    .unwrap_or_else(|| "[]".to_string()),
+            ),
         })
     }
 }
 
 impl Task for FerryTask {
     fn run(&self) {
-        *self.result.borrow_mut() = self.inner_run();
+        let started_at = Instant::now();
+        let result = self.inner_run();
+        if let Some(session) = &self.session {
+            session.record_phase(self.ferry.name(), started_at.elapsed());
+            if let Err(err) = &result {
+                session.record_error(err);
+            }
+        }
+        *self.result.borrow_mut() = result;
     }
 
     fn done(&self) -> result::Result<(), nsresult> {
@@ -277,6 +382,9 @@ pub struct ApplyTask {
     engine: Box<dyn BridgedEngine>,
     callback: ThreadPtrHandle<mozIBridgedSyncEngineApplyCallback>,
     result: AtomicRefCell<anyhow::Result<Vec<String>>>,
+    /// The sync session this apply is part of, if its bridge is tracking
+    /// one - see `FerryTask::session`.
+    session: Option<Arc<SyncSession>>,
 }
 
 impl ApplyTask {
@@ -289,8 +397,11 @@ impl ApplyTask {
     fn inner_run(&self) -> anyhow::Result<Vec<String>> {
         let ApplyResults {
             records: outgoing_records,
-            ..
+            num_reconciled,
         } = self.engine.apply()?;
+        if let (Some(session), Some(num_reconciled)) = (&self.session, num_reconciled) {
+            session.record_reconciled(num_reconciled as u64);
+        }
         let outgoing_records_json = outgoing_records
             .iter()
             .map(|record| Ok(serde_json::to_string(record)?))
@@ -302,6 +413,7 @@ impl ApplyTask {
     /// be called once, after the records are applied on the background thread.
     pub fn new(
         engine: Box<dyn BridgedEngine>,
+        session: Option<Arc<SyncSession>>,
         callback: &mozIBridgedSyncEngineApplyCallback,
     ) -> Result<ApplyTask> {
         Ok(ApplyTask {
@@ -311,6 +423,7 @@ impl ApplyTask {
                 RefPtr::new(callback),
             )?,
             result: AtomicRefCell::new(Err(Error::DidNotRun(Self::name()).into())),
+            session,
         })
     }
 
@@ -328,7 +441,15 @@ impl ApplyTask {
 
 impl Task for ApplyTask {
     fn run(&self) {
-        *self.result.borrow_mut() = self.inner_run();
+        let started_at = Instant::now();
+        let result = self.inner_run();
+        if let Some(session) = &self.session {
+            session.record_phase(Self::name(), started_at.elapsed());
+            if let Err(err) = &result {
+                session.record_error(err);
+            }
+        }
+        *self.result.borrow_mut() = result;
     }
 
     fn done(&self) -> result::Result<(), nsresult> {
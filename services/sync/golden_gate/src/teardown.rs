@@ -0,0 +1,120 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! With several bridged engines sharing the same infrastructure (background task
+//! queues, shared connections, etc), shutdown needs to tear them down in a
+//! specific order, and exactly once - tearing down in the wrong order, or twice,
+//! can mean using a connection after it's been closed. `TeardownCoordinator`
+//! centralizes that ordering and once-only bookkeeping as a small, synchronous
+//! utility, so each bridge doesn't have to reimplement it.
+//!
+//! This is deliberately narrower than a full "sequence each engine's own
+//! `TeardownTask`s on its own queue, and report stragglers to the shutdown
+//! blocker state" story: `run` just calls each participant's closure directly
+//! on the caller's thread, in priority order - it doesn't dispatch anything
+//! onto a bridge's own background queue, and it has no concept of a shutdown
+//! blocker. No bridge constructs one yet; wiring a `TeardownCoordinator` into
+//! `webext_storage_bridge` (or wherever the first multi-engine bridge lands)
+//! is follow-up work, not done here.
+
+use std::sync::Mutex;
+
+/// A single participant in an ordered shutdown. Lower `priority` values are torn
+/// down first - for example, an engine that depends on a shared connection should
+/// use a lower priority than the connection owner, so the connection is closed
+/// last.
+struct Participant {
+    name: &'static str,
+    priority: i32,
+    teardown: Box<dyn FnOnce() + Send>,
+}
+
+/// Coordinates an ordered, exactly-once teardown across multiple bridge instances.
+pub struct TeardownCoordinator {
+    participants: Mutex<Option<Vec<Participant>>>,
+}
+
+impl Default for TeardownCoordinator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TeardownCoordinator {
+    pub fn new() -> Self {
+        Self {
+            participants: Mutex::new(Some(Vec::new())),
+        }
+    }
+
+    /// Registers a teardown callback. Panics if called after `run` - registering
+    /// new participants once shutdown has started makes no sense, since they'd
+    /// never be torn down.
+    pub fn register(
+        &self,
+        name: &'static str,
+        priority: i32,
+        teardown: impl FnOnce() + Send + 'static,
+    ) {
+        let mut participants = self.participants.lock().unwrap();
+        match participants.as_mut() {
+            Some(participants) => participants.push(Participant {
+                name,
+                priority,
+                teardown: Box::new(teardown),
+            }),
+            None => panic!("Can't register `{name}` for teardown after shutdown has started"),
+        }
+    }
+
+    /// Runs every registered teardown exactly once, in ascending priority order.
+    /// Calling this more than once is a no-op after the first call.
+    pub fn run(&self) {
+        let mut participants = match self.participants.lock().unwrap().take() {
+            Some(participants) => participants,
+            None => return, // Already torn down.
+        };
+        participants.sort_by_key(|p| p.priority);
+        for participant in participants {
+            log::debug!("Tearing down `{}`", participant.name);
+            (participant.teardown)();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    #[test]
+    fn test_teardown_order() {
+        let coordinator = TeardownCoordinator::new();
+        let order = Arc::new(StdMutex::new(Vec::new()));
+
+        let o1 = Arc::clone(&order);
+        coordinator.register("engine", 10, move || o1.lock().unwrap().push("engine"));
+        let o2 = Arc::clone(&order);
+        coordinator.register("connection", 0, move || {
+            o2.lock().unwrap().push("connection")
+        });
+
+        coordinator.run();
+        // The connection has the lower priority, so it tears down first even
+        // though it was registered second.
+        assert_eq!(*order.lock().unwrap(), vec!["connection", "engine"]);
+
+        // Running again is a no-op.
+        coordinator.run();
+        assert_eq!(*order.lock().unwrap(), vec!["connection", "engine"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "after shutdown has started")]
+    fn test_register_after_run_panics() {
+        let coordinator = TeardownCoordinator::new();
+        coordinator.run();
+        coordinator.register("too-late", 0, || {});
+    }
+}
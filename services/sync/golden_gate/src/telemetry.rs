@@ -0,0 +1,118 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Accumulates what happens across the ferry tasks of one sync session -
+//! `SyncStarted` through `SyncFinished` - so `syncFinished` can resolve with
+//! a single structured summary instead of making its JS caller piece one
+//! together from several separate callbacks.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use sync15::Guid;
+
+/// Cap on how many finished syncs `SyncHistory` retains - `about:sync` only
+/// needs enough recent syncs to explain "why was the last sync slow", not a
+/// full log.
+const MAX_RECENT_SYNCS: usize = 10;
+
+/// One sync session's accumulated counters, phase timings, and errors.
+///
+/// Owned by the consuming bridge (eg `StorageSyncArea`), which creates a new
+/// one in its `SyncStarted` handler and hands an `Arc` of it to the
+/// `SyncStarted`, `StoreIncoming`, `SetUploaded` and `SyncFinished`
+/// `FerryTask`s and the `ApplyTask` it dispatches - those tasks record into
+/// it as they run, on whatever background thread they happen to execute on.
+/// Other ferries (eg `LastSync`, `SetLastSync`) aren't given it.
+#[derive(Default)]
+pub struct SyncSession {
+    trace_id: Mutex<Option<String>>,
+    state: Mutex<SessionState>,
+}
+
+#[derive(Default)]
+struct SessionState {
+    incoming_applied: u64,
+    outgoing_uploaded: u64,
+    reconciled: u64,
+    errors: Vec<String>,
+    // Keyed by `Ferry::name`/`ApplyTask::name` - summed rather than
+    // overwritten, since some phases (eg `storeIncoming`, `setUploaded`) run
+    // more than once per session.
+    phase_durations_ms: HashMap<&'static str, u64>,
+}
+
+impl SyncSession {
+    /// Starts a new session with a fresh trace ID, for `SyncStarted`.
+    pub fn new() -> Self {
+        Self {
+            trace_id: Mutex::new(Some(Guid::random().to_string())),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Records that `phase` (a ferry or apply task's name) took `duration`
+    /// to run.
+    pub(crate) fn record_phase(&self, phase: &'static str, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        *state.phase_durations_ms.entry(phase).or_insert(0) += duration.as_millis() as u64;
+    }
+
+    pub(crate) fn record_incoming_applied(&self, n: u64) {
+        self.state.lock().unwrap().incoming_applied += n;
+    }
+
+    pub(crate) fn record_outgoing_uploaded(&self, n: u64) {
+        self.state.lock().unwrap().outgoing_uploaded += n;
+    }
+
+    pub(crate) fn record_reconciled(&self, n: u64) {
+        self.state.lock().unwrap().reconciled += n;
+    }
+
+    pub(crate) fn record_error(&self, error: impl ToString) {
+        self.state.lock().unwrap().errors.push(error.to_string());
+    }
+
+    /// Builds the JSON summary for `SyncFinished` to ferry back to the JS
+    /// caller. `serde_json::Value` rather than a `Serialize` struct, since
+    /// this crate doesn't otherwise depend on `serde`'s derive macros.
+    pub fn to_json(&self) -> serde_json::Value {
+        let trace_id = self.trace_id.lock().unwrap().clone().unwrap_or_default();
+        let state = self.state.lock().unwrap();
+        serde_json::json!({
+            "traceId": trace_id,
+            "incomingApplied": state.incoming_applied,
+            "outgoingUploaded": state.outgoing_uploaded,
+            "reconciled": state.reconciled,
+            "errors": state.errors,
+            "phaseDurationsMs": state.phase_durations_ms,
+        })
+    }
+}
+
+/// A bounded history of recent `SyncSession::to_json()` summaries, for
+/// `getRecentSyncHistory`. Unlike `SyncSession`, which covers a single sync
+/// and is replaced every `SyncStarted`, this is owned by the bridge itself
+/// and outlives any one sync.
+#[derive(Default)]
+pub struct SyncHistory(Mutex<VecDeque<serde_json::Value>>);
+
+impl SyncHistory {
+    /// Records a finished sync's summary, pruning the oldest entry once
+    /// there are more than `MAX_RECENT_SYNCS`.
+    pub(crate) fn record(&self, summary: serde_json::Value) {
+        let mut sessions = self.0.lock().unwrap();
+        sessions.push_back(summary);
+        while sessions.len() > MAX_RECENT_SYNCS {
+            sessions.pop_front();
+        }
+    }
+
+    /// Returns the retained summaries, oldest first.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Array(self.0.lock().unwrap().iter().cloned().collect())
+    }
+}
@@ -109,11 +109,29 @@ pub fn open_database_with_flags<CI: ConnectionInitializer, P: AsRef<Path>>(
     open_flags: OpenFlags,
     connection_initializer: &CI,
 ) -> Result<Connection> {
-    do_open_database_with_flags(&path, open_flags, connection_initializer).or_else(|e| {
-        // See if we can recover from the error and try a second time
-        try_handle_db_failure(&path, open_flags, connection_initializer, e)?;
-        do_open_database_with_flags(&path, open_flags, connection_initializer)
-    })
+    open_database_with_flags_and_recovery_info(path, open_flags, connection_initializer)
+        .map(|(conn, _recovered)| conn)
+}
+
+/// Like [`open_database_with_flags`], but also reports whether the database had
+/// to be deleted and recreated to get there - ie whether `Error::Corrupt` was
+/// hit and silently recovered from. Callers that want to track how often that's
+/// happening (eg to detect a persistently corrupt disk, rather than one bad
+/// write) should use this instead.
+pub fn open_database_with_flags_and_recovery_info<CI: ConnectionInitializer, P: AsRef<Path>>(
+    path: P,
+    open_flags: OpenFlags,
+    connection_initializer: &CI,
+) -> Result<(Connection, bool)> {
+    match do_open_database_with_flags(&path, open_flags, connection_initializer) {
+        Ok(conn) => Ok((conn, false)),
+        Err(e) => {
+            // See if we can recover from the error and try a second time
+            try_handle_db_failure(&path, open_flags, connection_initializer, e)?;
+            do_open_database_with_flags(&path, open_flags, connection_initializer)
+                .map(|conn| (conn, true))
+        }
+    }
 }
 
 fn do_open_database_with_flags<CI: ConnectionInitializer, P: AsRef<Path>>(
@@ -26,6 +26,20 @@ pub struct RemoteClient {
     pub device_name: String,
     #[serde(default)]
     pub device_type: DeviceType,
+    /// Capabilities the client advertises (eg "sendTabCommand", "tabGroups").
+    /// Defaults to empty for older records that predate this field.
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// The client's OS (eg "Windows", "Darwin", "Android"), as reported in the
+    /// clients collection. `None` for clients that don't advertise it, or for
+    /// older records that predate this field.
+    #[serde(default)]
+    pub os: Option<String>,
+    /// The client's form factor (eg "desktop", "phone", "tablet"), as reported
+    /// in the clients collection. `None` for clients that don't advertise it,
+    /// or for older records that predate this field.
+    #[serde(default)]
+    pub form_factor: Option<String>,
 }
 
 #[cfg(test)]
@@ -37,10 +51,11 @@ mod client_types_tests {
         // Missing `device_type` gets DeviceType::Unknown.
         let dt = serde_json::from_str::<RemoteClient>("{\"device_name\": \"foo\"}").unwrap();
         assert_eq!(dt.device_type, DeviceType::Unknown);
-        // But reserializes as null.
+        // But reserializes as null - capabilities, os and form_factor also fall
+        // back to their defaults (empty / null) for records that predate them.
         assert_eq!(
             serde_json::to_string(&dt).unwrap(),
-            "{\"fxa_device_id\":null,\"device_name\":\"foo\",\"device_type\":null}"
+            "{\"fxa_device_id\":null,\"device_name\":\"foo\",\"device_type\":null,\"capabilities\":[],\"os\":null,\"form_factor\":null}"
         );
 
         // explicit null is also unknown.
@@ -62,7 +77,7 @@ mod client_types_tests {
         // The None gets re-serialized as null.
         assert_eq!(
             serde_json::to_string(&dt).unwrap(),
-            "{\"fxa_device_id\":null,\"device_name\":\"foo\",\"device_type\":null}"
+            "{\"fxa_device_id\":null,\"device_name\":\"foo\",\"device_type\":null,\"capabilities\":[],\"os\":null,\"form_factor\":null}"
         );
 
         // DeviceType::Unknown gets serialized as null.
@@ -70,22 +85,37 @@ mod client_types_tests {
             device_name: "bar".to_string(),
             fxa_device_id: None,
             device_type: DeviceType::Unknown,
+            capabilities: vec![],
+            os: None,
+            form_factor: None,
         };
         assert_eq!(
             serde_json::to_string(&dt).unwrap(),
-            "{\"fxa_device_id\":null,\"device_name\":\"bar\",\"device_type\":null}"
+            "{\"fxa_device_id\":null,\"device_name\":\"bar\",\"device_type\":null,\"capabilities\":[],\"os\":null,\"form_factor\":null}"
         );
 
-        // DeviceType::Desktop gets serialized as "desktop".
+        // DeviceType::Desktop gets serialized as "desktop", and a known os/form
+        // factor round-trip as plain strings.
         let dt = RemoteClient {
             device_name: "bar".to_string(),
             fxa_device_id: Some("fxa".to_string()),
             device_type: DeviceType::Desktop,
+            capabilities: vec![],
+            os: Some("Darwin".to_string()),
+            form_factor: Some("desktop".to_string()),
         };
         assert_eq!(
             serde_json::to_string(&dt).unwrap(),
-            "{\"fxa_device_id\":\"fxa\",\"device_name\":\"bar\",\"device_type\":\"desktop\"}"
+            "{\"fxa_device_id\":\"fxa\",\"device_name\":\"bar\",\"device_type\":\"desktop\",\"capabilities\":[],\"os\":\"Darwin\",\"form_factor\":\"desktop\"}"
         );
+
+        // Old rows that predate `os`/`form_factor` deserialize with both `None`.
+        let dt = serde_json::from_str::<RemoteClient>(
+            "{\"fxa_device_id\":\"fxa\",\"device_name\":\"bar\",\"device_type\":\"desktop\",\"capabilities\":[]}",
+        )
+        .unwrap();
+        assert_eq!(dt.os, None);
+        assert_eq!(dt.form_factor, None);
     }
 
     #[test]
@@ -99,6 +129,7 @@ mod client_types_tests {
                         fxa_device_id: None,
                         device_name: "my device".to_string(),
                         device_type: DeviceType::Unknown,
+                        capabilities: vec![],
                     },
                 ),
                 (
@@ -107,6 +138,7 @@ mod client_types_tests {
                         fxa_device_id: None,
                         device_name: "device with no tabs".to_string(),
                         device_type: DeviceType::Unknown,
+                        capabilities: vec![],
                     },
                 ),
                 (
@@ -115,6 +147,7 @@ mod client_types_tests {
                         fxa_device_id: None,
                         device_name: "device with a tab".to_string(),
                         device_type: DeviceType::Desktop,
+                        capabilities: vec![],
                     },
                 ),
             ]),
@@ -477,16 +477,25 @@ mod tests {
                 fxa_device_id: Some("deviceAAAAAA".to_string()),
                 device_name: "Laptop".into(),
                 device_type: DeviceType::Desktop,
+                capabilities: vec![],
+                os: None,
+                form_factor: None,
             },
             RemoteClient {
                 fxa_device_id: Some("iPhooooooone".to_string()),
                 device_name: "iPhone".into(),
                 device_type: DeviceType::Mobile,
+                capabilities: vec![],
+                os: None,
+                form_factor: None,
             },
             RemoteClient {
                 fxa_device_id: Some("deviceCCCCCC".to_string()),
                 device_name: "Fenix".into(),
                 device_type: DeviceType::Mobile,
+                capabilities: vec![],
+                os: None,
+                form_factor: None,
             },
         ];
         let actual_remote_clients = expected_ids
@@ -607,11 +616,17 @@ mod tests {
                 fxa_device_id: Some("deviceAAAAAA".to_string()),
                 device_name: "Laptop".into(),
                 device_type: DeviceType::Desktop,
+                capabilities: vec![],
+                os: None,
+                form_factor: None,
             },
             RemoteClient {
                 fxa_device_id: Some("iPhooooooone".to_string()),
                 device_name: "iPhone".into(),
                 device_type: DeviceType::Mobile,
+                capabilities: vec![],
+                os: None,
+                form_factor: None,
             },
         ];
         let actual_remote_clients = expected_ids
@@ -744,11 +759,17 @@ mod tests {
                 fxa_device_id: Some("deviceAAAAAA".to_string()),
                 device_name: "Laptop".into(),
                 device_type: DeviceType::Desktop,
+                capabilities: vec![],
+                os: None,
+                form_factor: None,
             },
             RemoteClient {
                 fxa_device_id: Some("iPhooooooone".to_string()),
                 device_name: "iPhone".into(),
                 device_type: DeviceType::Mobile,
+                capabilities: vec![],
+                os: None,
+                form_factor: None,
             },
         ];
         let actual_remote_clients = expected_ids
@@ -24,9 +24,10 @@ pub struct ClientRecord {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub fxa_device_id: Option<String>,
 
-    /// `version`, `protocols`, `formfactor`, `os`, `appPackage`, `application`,
-    /// and `device` are unused and optional in all implementations (Desktop,
-    /// iOS, and Fennec), but we round-trip them.
+    /// `version`, `protocols`, `appPackage`, `application`, and `device` are
+    /// unused and optional in all implementations (Desktop, iOS, and Fennec),
+    /// but we round-trip them. `formfactor` and `os` are surfaced on
+    /// `RemoteClient` - see the `From` impl below - for better icons/grouping.
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
@@ -63,6 +64,9 @@ impl From<&ClientRecord> for crate::RemoteClient {
             fxa_device_id: record.fxa_device_id.clone(),
             device_name: record.name.clone(),
             device_type: record.typ,
+            capabilities: vec![],
+            os: record.os.clone(),
+            form_factor: record.form_factor.clone(),
         }
     }
 }
@@ -158,6 +162,34 @@ impl From<Command> for CommandRecord {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_remote_client_from_client_record_carries_os_and_form_factor() {
+        let record: ClientRecord = serde_json::from_value(serde_json::json!({
+            "id": "device1",
+            "name": "My Phone",
+            "type": "mobile",
+            "os": "iOS",
+            "formfactor": "phone",
+        }))
+        .unwrap();
+        let remote_client = crate::RemoteClient::from(&record);
+        assert_eq!(remote_client.os, Some("iOS".to_string()));
+        assert_eq!(remote_client.form_factor, Some("phone".to_string()));
+    }
+
+    #[test]
+    fn test_remote_client_from_client_record_defaults_missing_os_and_form_factor() {
+        let record: ClientRecord = serde_json::from_value(serde_json::json!({
+            "id": "device1",
+            "name": "An Old Client",
+            "type": "desktop",
+        }))
+        .unwrap();
+        let remote_client = crate::RemoteClient::from(&record);
+        assert_eq!(remote_client.os, None);
+        assert_eq!(remote_client.form_factor, None);
+    }
+
     #[test]
     fn test_valid_commands() {
         let ser = serde_json::json!({"command": "wipeEngine", "args": ["foo"]});
@@ -91,6 +91,34 @@ pub trait BridgedEngine: Send + Sync {
     /// Erases all local user data for this collection, and any Sync metadata.
     /// This method is destructive, and unused for most collections.
     fn wipe(&self) -> Result<()>;
+
+    /// Temporarily freezes this engine's syncing without affecting global
+    /// sync, for troubleshooting. Implementations that don't support pausing
+    /// can ignore this - it defaults to a no-op.
+    fn pause(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Resumes syncing after a previous `pause()`. Also defaults to a no-op.
+    fn resume(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether this engine is ready for `store_incoming`/`apply` - most
+    /// bridged engines don't have an unconfigured state, so this defaults to
+    /// `true`. An engine that does (eg one that needs `prepare_for_sync` to
+    /// run first) should override this to report it for diagnostics.
+    fn is_configured(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Whether this engine has put itself into a degraded state it can't sync
+    /// out of without intervention (eg repeated storage corruption) - most
+    /// bridged engines have no such state, so this defaults to `false`. An
+    /// engine that does should override this to report it for diagnostics.
+    fn is_degraded(&self) -> Result<bool> {
+        Ok(false)
+    }
 }
 
 // This is an adaptor trait - the idea is that engines can implement this
@@ -107,6 +135,19 @@ pub trait BridgedEngineAdaptor: Send + Sync {
     fn sync_started(&self) -> Result<()> {
         Ok(())
     }
+    fn pause(&self) -> Result<()> {
+        Ok(())
+    }
+    fn resume(&self) -> Result<()> {
+        Ok(())
+    }
+    fn is_configured(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn is_degraded(&self) -> Result<bool> {
+        Ok(false)
+    }
 
     fn engine(&self) -> &dyn SyncEngine;
 }
@@ -166,6 +207,22 @@ impl<A: BridgedEngineAdaptor> BridgedEngine for A {
         A::sync_started(self)
     }
 
+    fn pause(&self) -> Result<()> {
+        A::pause(self)
+    }
+
+    fn resume(&self) -> Result<()> {
+        A::resume(self)
+    }
+
+    fn is_configured(&self) -> Result<bool> {
+        A::is_configured(self)
+    }
+
+    fn is_degraded(&self) -> Result<bool> {
+        A::is_degraded(self)
+    }
+
     fn store_incoming(&self, incoming_records: Vec<IncomingBso>) -> Result<()> {
         let engine = self.engine();
         let mut telem = telemetry::Engine::new(engine.collection_name());
@@ -2,6 +2,76 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const FULL_SYNC_ONLY_BEGIN: &str = "// BEGIN FULL-SYNC-ONLY";
+const FULL_SYNC_ONLY_END: &str = "// END FULL-SYNC-ONLY";
+const TEST_SUPPORT_ONLY_BEGIN: &str = "// BEGIN TEST-SUPPORT-ONLY";
+const TEST_SUPPORT_ONLY_END: &str = "// END TEST-SUPPORT-ONLY";
+
 fn main() {
-    uniffi::generate_scaffolding("./src/tabs.udl").unwrap();
+    println!("cargo:rerun-if-changed=src/tabs.udl");
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_FULL_SYNC");
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_TEST_SUPPORT");
+
+    let full_sync = env::var("CARGO_FEATURE_FULL_SYNC").is_ok();
+    let test_support = env::var("CARGO_FEATURE_TEST_SUPPORT").is_ok();
+
+    if full_sync && test_support {
+        // The common case: every optional surface is compiled in, so ship
+        // the UDL exactly as checked in.
+        uniffi::generate_scaffolding("./src/tabs.udl").unwrap();
+        return;
+    }
+
+    // At least one optional surface is disabled - strip the UDL section(s)
+    // describing the Rust symbols that aren't compiled in for this build
+    // (see the matching comments in src/tabs.udl), since they'd otherwise
+    // reference types that don't exist.
+    let udl = fs::read_to_string("./src/tabs.udl").expect("failed to read src/tabs.udl");
+    let mut filtered_udl = udl;
+    if !full_sync {
+        // `sync::engine`/`sync::bridge` aren't compiled in - see `full-sync`'s
+        // doc comment in Cargo.toml.
+        filtered_udl =
+            strip_marked_sections(&filtered_udl, FULL_SYNC_ONLY_BEGIN, FULL_SYNC_ONLY_END);
+    }
+    if !test_support {
+        // `test_fixtures` isn't compiled in - see `test-support`'s doc
+        // comment in Cargo.toml.
+        filtered_udl = strip_marked_sections(
+            &filtered_udl,
+            TEST_SUPPORT_ONLY_BEGIN,
+            TEST_SUPPORT_ONLY_END,
+        );
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let filtered_udl_path = out_dir.join("tabs-filtered.udl");
+    fs::write(&filtered_udl_path, filtered_udl).expect("failed to write filtered UDL");
+    uniffi::generate_scaffolding(filtered_udl_path.to_str().unwrap()).unwrap();
+}
+
+/// Removes every `begin` ... `end` block (inclusive) from `udl` - see the
+/// matching comments in src/tabs.udl.
+fn strip_marked_sections(udl: &str, begin: &str, end: &str) -> String {
+    let mut result = String::with_capacity(udl.len());
+    let mut in_section = false;
+    for line in udl.lines() {
+        if line.trim_start().starts_with(begin) {
+            in_section = true;
+            continue;
+        }
+        if line.trim_start().starts_with(end) {
+            in_section = false;
+            continue;
+        }
+        if !in_section {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    result
 }
@@ -0,0 +1,110 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Interactive debugging helpers for xpcshell and the browser console. Gated
+//! behind the `debug-tools` feature so this never ships in a release build -
+//! see `TabsStore::execute_debug_command`.
+//!
+//! Unlike the rest of the public interface, commands here are dispatched by
+//! name from a single uniffi method rather than getting one method each, since
+//! they only exist to be typed by hand while poking at a store - adding a
+//! proper method per command would suggest they're meant for production
+//! callers.
+
+use crate::error::*;
+use crate::schema;
+use crate::storage::TabsStorage;
+use crate::sync::record::TabsRecord;
+use sync15::ServerTimestamp;
+
+#[derive(serde_derive::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SimulateIncomingArgs {
+    server_timestamp_millis: i64,
+    records: Vec<TabsRecord>,
+}
+
+/// Runs `name` against `storage`, returning a JSON-encoded result. `args_json`
+/// is command-specific and ignored by commands that don't need it.
+pub(crate) fn execute(storage: &mut TabsStorage, name: &str, args_json: &str) -> Result<String> {
+    Ok(match name {
+        "dump-meta" => storage.dump_meta_json()?,
+
+        "dump-clients" => serde_json::to_string(&storage.get_remote_tabs(true))?,
+
+        // Mirrors what a large-enough server timestamp regression already does in
+        // `TabsEngine::set_last_sync` - forces the next sync to re-fetch the whole
+        // `tabs` collection instead of trusting our incremental state.
+        "force-dirty" => {
+            storage.put_meta(schema::FORCE_MIRROR_REFRESH_KEY, &true)?;
+            "true".to_string()
+        }
+
+        // Feeds hand-written records through the same path a real sync's
+        // `store_incoming`/`apply` would, without needing a sync server or a
+        // second device.
+        "simulate-incoming" => {
+            let args: SimulateIncomingArgs = serde_json::from_str(args_json)?;
+            let timestamp = ServerTimestamp::from_millis(args.server_timestamp_millis);
+            let records = args.records.into_iter().map(|r| (r, timestamp)).collect();
+            storage.replace_remote_tabs(records)?;
+            "true".to_string()
+        }
+
+        other => return Err(Error::UnknownDebugCommand(other.to_string())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_command_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = TabsStorage::new(dir.path().join("test_debug_unknown.db"));
+        let err = execute(&mut storage, "dump-everything", "").unwrap_err();
+        assert!(matches!(err, Error::UnknownDebugCommand(name) if name == "dump-everything"));
+    }
+
+    #[test]
+    fn test_force_dirty_sets_mirror_refresh_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = TabsStorage::new(dir.path().join("test_debug_force_dirty.db"));
+        execute(&mut storage, "force-dirty", "").unwrap();
+        assert_eq!(
+            storage
+                .get_meta::<bool>(schema::FORCE_MIRROR_REFRESH_KEY)
+                .unwrap(),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_simulate_incoming_lands_in_remote_tabs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = TabsStorage::new(dir.path().join("test_debug_simulate_incoming.db"));
+        let args = serde_json::json!({
+            "serverTimestampMillis": 1000,
+            "records": [{
+                "id": "device-1",
+                "clientName": "desktop",
+                "tabs": [{
+                    "title": "example",
+                    "urlHistory": ["https://example.com/"],
+                    "icon": null,
+                    "lastUsed": 1000,
+                }],
+            }],
+        })
+        .to_string();
+
+        execute(&mut storage, "simulate-incoming", &args).unwrap();
+
+        let remote_tabs = storage.get_remote_tabs(true).unwrap();
+        assert_eq!(remote_tabs.len(), 1);
+        assert_eq!(remote_tabs[0].client_id, "device-1");
+        assert_eq!(remote_tabs[0].remote_tabs[0].title, "example");
+    }
+}
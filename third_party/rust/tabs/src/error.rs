@@ -20,6 +20,58 @@ pub enum TabsApiError {
 
     #[error("Unexpected tabs error: {reason}")]
     UnexpectedTabsError { reason: String },
+
+    // Distinct from `SqlError` so the app can tell "disk full" apart from other,
+    // probably-unrecoverable, SQL errors and respond accordingly (eg prompt the
+    // user to free up space) instead of treating it as a bug to report.
+    #[error("DiskFullError: {reason}")]
+    DiskFullError { reason: String },
+
+    // Returned by `TabsStore::validate_db_path` - the configured path isn't
+    // usable (missing/unwritable parent directory, a directory where the DB
+    // file should be, or not enough room for the initial schema). Surfaced
+    // before the first real operation rather than as a confusing raw SQLite
+    // error the first time something tries to open the DB.
+    #[error("InvalidDatabasePathError: {reason}")]
+    InvalidDatabasePathError { reason: String },
+
+    // Returned by `TabsBridgedEngine::sync_started` while paused (see
+    // `TabsBridgedEngine::pause`), so the orchestrator can tell "we
+    // deliberately skipped this sync" apart from a real failure.
+    #[error("Sync is paused")]
+    SyncPaused,
+
+    // Returned by `TabsBridgedEngine::store_incoming`/`apply` (and the
+    // sync-manager-registered path) when called before `prepare_for_sync` has
+    // told us which client we are - see `TabsEngine::require_configured`.
+    // Distinct from `UnexpectedTabsError` so a caller that forgot the call can
+    // tell that apart from a real bug.
+    #[error("Sync engine used before prepare_for_sync configured it")]
+    NotConfigured,
+
+    // Returned by `TabsBridgedEngine::sync_started` once the database has
+    // been deleted and recreated due to corruption too many times in a row -
+    // see `TabsStorage::record_corruption_event`. Distinct from `SqlError` so
+    // the caller can tell "this needs a human, not a retry" apart from a
+    // one-off SQL failure.
+    #[error("Tabs database is degraded after repeated corruption")]
+    DatabaseDegraded,
+
+    // Returned by `TabsBridgedEngine::store_incoming`/`apply` (and the
+    // sync-manager-registered path) when `TabsEngine::abort_sync` interrupted
+    // them mid-call - eg the browser going offline. Distinct from the
+    // generic `UnexpectedTabsError` an embedder-installed `Interruptee`
+    // collapses to, so the caller can tell "we deliberately stopped this
+    // sync" apart from a real failure, the same as `SyncPaused` does.
+    #[error("Sync was aborted")]
+    SyncAborted,
+
+    // Returned by any `TabsStore` operation called after `TabsStore::shutdown`
+    // has closed the underlying connection - see `TabsStorage::close`.
+    // Distinct from `UnexpectedTabsError` so a caller that raced its own
+    // shutdown can tell that apart from a real bug.
+    #[error("Tabs store has already been shut down")]
+    AlreadyTornDown,
 }
 
 // Error we use internally
@@ -50,42 +102,236 @@ pub enum Error {
 
     #[error("Error opening database: {0}")]
     OpenDatabaseError(#[from] sql_support::open_database::Error),
+
+    // Raised by `export::run_export` and `import::run_import` - the
+    // embedder-supplied path couldn't be created/read/written, or the gzip
+    // stream couldn't be flushed. Collapses to `UnexpectedTabsError` like
+    // `JsonError`/`UrlParseError` above, since there's nothing
+    // export/import-specific a caller would do differently with it.
+    #[cfg(feature = "debug-tools")]
+    #[error("Error reading/writing dump file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    // Raised in place of `SqlError` when we detect SQLITE_FULL, and when a write is
+    // rejected outright because we're still backing off from a previous one - see
+    // `TabsStorage::note_disk_full`.
+    #[error("The disk is full")]
+    DiskFull,
+
+    // Only reachable via `TabsStore::execute_debug_command`, which is driven by
+    // hand-typed input from an interactive shell rather than another caller in
+    // this crate.
+    #[cfg(feature = "debug-tools")]
+    #[error("Unknown debug command: {0}")]
+    UnknownDebugCommand(String),
+
+    // Only reachable via `TabsStore::load_test_fixture`, which is driven by
+    // hand-typed fixture names from xpcshell test helpers.
+    #[cfg(feature = "test-support")]
+    #[error("Unknown test fixture: {0}")]
+    UnknownTestFixture(String),
+
+    // Raised by `TabsBridgedEngineAdaptor::sync_started` while paused. Crosses
+    // the bridge's `anyhow::Result` boundary and is downcast back to this type
+    // there, so it can be reported as `TabsApiError::SyncPaused` rather than
+    // the generic `UnexpectedTabsError` every other `anyhow::Error` collapses to.
+    #[error("Sync is paused")]
+    SyncPaused,
+
+    // Raised by `storage::encode_record`/`decode_record` - either a bincode
+    // (de)serialization failure, or a `record`/`format` pair this build
+    // doesn't know how to read (eg a bincode row with `bincode-mirror`
+    // disabled, or an unrecognized format tag from a future build).
+    #[error("Error encoding/decoding a tabs record: {0}")]
+    RecordCodecError(String),
+
+    // Raised by `TabsBridgedEngine::convert_incoming_bsos` - a defensive bound
+    // on a single incoming envelope's size, so one corrupt or hostile record
+    // can't force an unbounded allocation before we've even validated its
+    // JSON. See the doc comment there.
+    #[error("Incoming envelope of {0} bytes exceeds the per-envelope size bound")]
+    IncomingEnvelopeTooLarge(usize),
+
+    // Raised by `TabsEngine::require_configured`. Crosses the bridge's
+    // `anyhow::Result` the same way `SyncPaused` does, so it can be reported
+    // as `TabsApiError::NotConfigured` instead of collapsing to the generic
+    // `UnexpectedTabsError` every other `anyhow::Error` gets.
+    #[error("Sync engine used before prepare_for_sync configured it")]
+    NotConfigured,
+
+    // Raised by `TabsEngine::require_not_degraded`. Crosses the bridge's
+    // `anyhow::Result` boundary the same way `SyncPaused`/`NotConfigured` do,
+    // so it's reported as `TabsApiError::DatabaseDegraded` instead of
+    // collapsing to the generic `UnexpectedTabsError`.
+    #[error("Tabs database is degraded after repeated corruption")]
+    DatabaseDegraded,
+
+    // Raised by `TabsEngine::require_not_aborted`. Crosses the bridge's
+    // `anyhow::Result` boundary the same way `SyncPaused`/`NotConfigured` do,
+    // so it's reported as `TabsApiError::SyncAborted` instead of collapsing
+    // to the generic `UnexpectedTabsError`.
+    #[error("Sync was aborted")]
+    SyncAborted,
+
+    // Raised by `TabsStorage::validate_db_path` - see
+    // `TabsApiError::InvalidDatabasePathError`.
+    #[error("Invalid database path: {reason}")]
+    InvalidDatabasePath { reason: String },
+
+    // Raised by `TabsStorage::open_if_exists`/`open_or_create` once
+    // `TabsStore::shutdown` has closed the connection - see
+    // `TabsApiError::AlreadyTornDown`.
+    #[error("Tabs store has already been shut down")]
+    AlreadyTornDown,
+}
+
+/// Whether `e` is SQLite telling us the disk (or quota) is full, as opposed to some
+/// other - probably more serious - SQL error.
+pub(crate) fn is_disk_full_error(e: &rusqlite::Error) -> bool {
+    matches!(
+        e,
+        rusqlite::Error::SqliteFailure(ffi_err, _) if ffi_err.code == rusqlite::ErrorCode::DiskFull
+    )
 }
 
 // Define how our internal errors are handled and converted to external errors
 // See `support/error/README.md` for how this works, especially the warning about PII.
-impl GetErrorHandling for Error {
-    type ExternalError = TabsApiError;
-
-    fn get_error_handling(&self) -> ErrorHandling<Self::ExternalError> {
-        match self {
-            Self::SyncAdapterError(e) => ErrorHandling::convert(TabsApiError::SyncError {
-                reason: e.to_string(),
-            })
-            .report_error("tabs-sync-error"),
-            Self::JsonError(e) => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
-                reason: e.to_string(),
-            })
-            .report_error("tabs-json-error"),
-            Self::MissingLocalIdError => {
-                ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
-                    reason: "MissingLocalId".to_string(),
-                })
-                .report_error("tabs-missing-local-id-error")
+//
+// This crate crosses the app boundary via uniffi rather than XPCOM, so there's no
+// nsresult to map to - `TabsApiError` variants play that role instead: eg
+// `DatabaseDegraded` for repeated corruption, `SyncAborted`/`SyncPaused` for an
+// interrupted sync, and `AlreadyTornDown` for use-after-shutdown. Each variant's
+// `#[error(...)]` message is the side channel a caller branches on - uniffi
+// surfaces it as the exception's message on every generated binding, JS
+// included, so there's no separate callback parameter needed for it. As the
+// taxonomy grows it's easy for a new `Error` variant to be added without a
+// thought-out external category, silently falling back to whatever arm happens
+// to match first (or failing to compile, if we're lucky). `error_handling_table!`
+// generates both this `impl` and a list of report tags from one place, so
+// `error_report_tags_are_distinct` below can catch an accidental collision, and
+// the match itself stays exhaustive - the compiler rejects a new `Error` variant
+// with no corresponding row.
+macro_rules! error_handling_table {
+    ($($(#[$attr:meta])? $pat:pat $(if $guard:expr)? => $handler:expr, $tag:expr;)*) => {
+        impl GetErrorHandling for Error {
+            type ExternalError = TabsApiError;
+
+            fn get_error_handling(&self) -> ErrorHandling<Self::ExternalError> {
+                match self {
+                    $($(#[$attr])? $pat $(if $guard)? => $handler,)*
+                }
             }
-            Self::UrlParseError(e) => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
-                reason: e.to_string(),
-            })
-            .report_error("tabs-url-parse-error"),
-            Self::SqlError(e) => ErrorHandling::convert(TabsApiError::SqlError {
-                reason: e.to_string(),
-            })
-            .report_error("tabs-sql-error"),
-            Self::OpenDatabaseError(e) => ErrorHandling::convert(TabsApiError::SqlError {
-                reason: e.to_string(),
-            })
-            .report_error("tabs-open-database-error"),
         }
+
+        #[cfg(test)]
+        const ERROR_REPORT_TAGS: &[&str] = &[$($tag,)*];
+    };
+}
+
+error_handling_table! {
+    Self::SyncAdapterError(e) => ErrorHandling::convert(TabsApiError::SyncError {
+        reason: e.to_string(),
+    })
+    .report_error("tabs-sync-error"), "tabs-sync-error";
+
+    Self::JsonError(e) => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
+        reason: e.to_string(),
+    })
+    .report_error("tabs-json-error"), "tabs-json-error";
+
+    Self::MissingLocalIdError => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
+        reason: "MissingLocalId".to_string(),
+    })
+    .report_error("tabs-missing-local-id-error"), "tabs-missing-local-id-error";
+
+    Self::UrlParseError(e) => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
+        reason: e.to_string(),
+    })
+    .report_error("tabs-url-parse-error"), "tabs-url-parse-error";
+
+    Self::SqlError(e) if is_disk_full_error(e) => ErrorHandling::convert(TabsApiError::DiskFullError {
+        reason: e.to_string(),
+    })
+    .log_warning(), "tabs-disk-full-detected";
+
+    Self::SqlError(e) => ErrorHandling::convert(TabsApiError::SqlError {
+        reason: e.to_string(),
+    })
+    .report_error("tabs-sql-error"), "tabs-sql-error";
+
+    Self::OpenDatabaseError(e) => ErrorHandling::convert(TabsApiError::SqlError {
+        reason: e.to_string(),
+    })
+    .report_error("tabs-open-database-error"), "tabs-open-database-error";
+
+    #[cfg(feature = "debug-tools")]
+    Self::IoError(e) => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
+        reason: e.to_string(),
+    })
+    .report_error("tabs-dump-io-error"), "tabs-dump-io-error";
+
+    // Already logged when it was first detected, in `note_disk_full`.
+    Self::DiskFull => ErrorHandling::convert(TabsApiError::DiskFullError {
+        reason: "disk is full".to_string(),
+    }), "tabs-disk-full-backoff-active";
+
+    #[cfg(feature = "debug-tools")]
+    Self::UnknownDebugCommand(name) => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
+        reason: format!("unknown debug command: {name}"),
+    }), "tabs-unknown-debug-command";
+
+    #[cfg(feature = "test-support")]
+    Self::UnknownTestFixture(name) => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
+        reason: format!("unknown test fixture: {name}"),
+    }), "tabs-unknown-test-fixture";
+
+    // Not a bug - no need to log or report it.
+    Self::SyncPaused => ErrorHandling::convert(TabsApiError::SyncPaused), "tabs-sync-paused";
+
+    Self::RecordCodecError(reason) => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
+        reason: reason.clone(),
+    })
+    .report_error("tabs-record-codec-error"), "tabs-record-codec-error";
+
+    Self::IncomingEnvelopeTooLarge(len) => ErrorHandling::convert(TabsApiError::UnexpectedTabsError {
+        reason: format!("incoming envelope of {len} bytes exceeds the per-envelope size bound"),
+    })
+    .report_error("tabs-incoming-envelope-too-large"), "tabs-incoming-envelope-too-large";
+
+    // Not a bug - same reasoning as `SyncPaused` above.
+    Self::NotConfigured => ErrorHandling::convert(TabsApiError::NotConfigured), "tabs-not-configured";
+
+    // Not a bug in itself, and expected to recur on every sync attempt while
+    // degraded - the corruption events that led here were already reported
+    // as they happened, in `TabsStorage::record_corruption_event`.
+    Self::DatabaseDegraded => ErrorHandling::convert(TabsApiError::DatabaseDegraded), "tabs-database-degraded";
+
+    // Not a bug - same reasoning as `SyncPaused` above.
+    Self::SyncAborted => ErrorHandling::convert(TabsApiError::SyncAborted), "tabs-sync-aborted";
+
+    Self::InvalidDatabasePath { reason } => ErrorHandling::convert(TabsApiError::InvalidDatabasePathError {
+        reason: reason.clone(),
+    })
+    .log_warning(), "tabs-invalid-database-path";
+
+    // Not a bug - a caller using the store after its own `shutdown` call is
+    // a lifecycle mistake on their end, not something worth reporting here.
+    Self::AlreadyTornDown => ErrorHandling::convert(TabsApiError::AlreadyTornDown), "tabs-already-torn-down";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_error_report_tags_are_distinct() {
+        let unique: HashSet<&&str> = ERROR_REPORT_TAGS.iter().collect();
+        assert_eq!(
+            unique.len(),
+            ERROR_REPORT_TAGS.len(),
+            "two Error variants share a report tag - give the new one its own"
+        );
     }
 }
 
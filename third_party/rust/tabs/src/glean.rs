@@ -0,0 +1,32 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Desktop is migrating sync telemetry off the legacy sync ping and onto Glean.
+//! The `tabs` crate doesn't (and shouldn't) depend on `glean` directly - the
+//! actual metric definitions live in the consuming app's `metrics.yaml` - so
+//! this just exposes an observer the embedder can wire up to record Glean
+//! metrics as we sync, in addition to (or eventually instead of) the ping.
+//! Only compiled in when the `glean-metrics` feature is enabled.
+
+#[cfg(feature = "glean-metrics")]
+use std::sync::Arc;
+
+#[cfg(feature = "glean-metrics")]
+pub trait GleanMetricsObserver: Send + Sync {
+    /// Called once per `apply()` with how long it took.
+    fn apply_duration(&self, duration_ms: u64);
+    /// Called once per sync with the number of records staged and uploaded.
+    fn incoming_outgoing_counts(&self, incoming: u32, outgoing: u32);
+    /// Called when `set_uploaded` confirms a local tabs snapshot reached the
+    /// server, with the end-to-end latency (ms) since it was captured by
+    /// `set_local_tabs` - for a distribution metric of how stale synced tabs
+    /// typically are.
+    fn upload_latency_ms(&self, latency_ms: u64);
+    /// Called whenever an operation fails, with a coarse error category
+    /// (eg "sql", "network", "unexpected") rather than the raw message.
+    fn error_category(&self, category: &str);
+}
+
+#[cfg(feature = "glean-metrics")]
+pub(crate) type GleanObserverHandle = std::sync::RwLock<Option<Arc<dyn GleanMetricsObserver>>>;
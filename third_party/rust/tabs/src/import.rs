@@ -0,0 +1,328 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Reads back a gzip-compressed NDJSON dump produced by `export::run_export`
+//! and applies it to the local mirror, on a background thread - the read
+//! side of that feature, so support can reproduce a user's reported state
+//! locally. Each line is parsed and validated independently, so one bad
+//! record doesn't abort the whole import; `dry_run` runs that same
+//! validation without writing anything, so a dump can be sanity-checked
+//! before it's applied. Modeled on `export`'s background thread and
+//! cooperative-cancellation handle.
+//!
+//! Gated behind `debug-tools` like the rest of this family - not something a
+//! real caller should ever depend on.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use flate2::read::GzDecoder;
+use sync15::ServerTimestamp;
+
+use crate::error::{ApiResult, Error};
+use crate::storage::ClientRemoteTabs;
+use crate::store::TabsStore;
+use crate::sync::record::TabsRecord;
+
+// Records applied per `TabsStorage::replace_remote_tabs` call - bounds how
+// much of the import is lost if the process dies mid-way, the same way
+// `export::EXPORT_CHUNK_SIZE` bounds a single progress report.
+const IMPORT_BATCH_SIZE: usize = 50;
+
+/// Implemented by the embedder to track a `TabsStore::import_from_file` run.
+/// Called from the dedicated import thread, never the caller's thread.
+pub trait ImportProgressCallback: Send + Sync {
+    /// Called once per NDJSON line, after it's been parsed and validated.
+    /// `error` is `None` for a record that passed validation; a validation
+    /// failure doesn't stop the import, it's just counted in the eventual
+    /// `ImportSummary`.
+    fn on_record(&self, line_number: u64, error: Option<String>);
+    /// Called exactly once, whether the import finished, failed, or was
+    /// cancelled.
+    fn on_complete(&self, result: ApiResult<ImportSummary>);
+}
+
+/// Outcome of a `TabsStore::import_from_file` run, passed to
+/// `ImportProgressCallback::on_complete`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub records_valid: u64,
+    pub records_invalid: u64,
+    /// `false` for a `dry_run` import (nothing is ever written), and also
+    /// `false` if `TabsImportHandle::cancel` stopped the import before any
+    /// batch was applied.
+    pub applied: bool,
+    /// `false` if the import was cancelled before reaching the end of the
+    /// file - mirrors `rebuild_filter_index_chunked`'s "interrupted is not
+    /// an error" convention, so cancellation shows up here rather than as
+    /// an `Err`.
+    pub completed: bool,
+}
+
+/// Owns the background thread started by `TabsStore::import_from_file`.
+/// Dropping this (or calling `cancel`) signals the thread to stop after its
+/// current record, rather than partway through applying a batch.
+pub struct TabsImportHandle {
+    cancelled: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TabsImportHandle {
+    pub(crate) fn start(
+        store: Arc<TabsStore>,
+        path: PathBuf,
+        dry_run: bool,
+        callback: Arc<dyn ImportProgressCallback>,
+    ) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = cancelled.clone();
+        let thread = std::thread::spawn(move || {
+            let result = run_import(&store, &path, dry_run, &thread_cancelled, callback.as_ref());
+            callback.on_complete(result);
+        });
+        Self {
+            cancelled,
+            thread: Some(thread),
+        }
+    }
+
+    /// Requests the import stop after its current record. `on_complete`
+    /// still fires, with `completed: false`.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for TabsImportHandle {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        // Bounded by how long the import thread takes to notice and finish
+        // applying its current batch - acceptable since dropping the handle
+        // is already a "stop what you're doing" style operation.
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_import(
+    store: &Arc<TabsStore>,
+    path: &PathBuf,
+    dry_run: bool,
+    cancelled: &AtomicBool,
+    callback: &dyn ImportProgressCallback,
+) -> ApiResult<ImportSummary> {
+    let file = File::open(path).map_err(Error::IoError)?;
+    let reader = BufReader::new(GzDecoder::new(file));
+
+    let mut summary = ImportSummary::default();
+    let mut batch: Vec<(TabsRecord, ServerTimestamp)> = Vec::with_capacity(IMPORT_BATCH_SIZE);
+
+    for (index, line) in reader.lines().enumerate() {
+        if cancelled.load(Ordering::SeqCst) {
+            apply_batch(store, &mut batch, dry_run, &mut summary)?;
+            return Ok(summary);
+        }
+        let line_number = index as u64 + 1;
+        let line = line.map_err(Error::IoError)?;
+        match validate_line(&line) {
+            Ok(record) => {
+                summary.records_valid += 1;
+                callback.on_record(line_number, None);
+                batch.push(record);
+                if batch.len() >= IMPORT_BATCH_SIZE {
+                    apply_batch(store, &mut batch, dry_run, &mut summary)?;
+                }
+            }
+            Err(reason) => {
+                summary.records_invalid += 1;
+                callback.on_record(line_number, Some(reason));
+            }
+        }
+    }
+    apply_batch(store, &mut batch, dry_run, &mut summary)?;
+    summary.completed = true;
+    Ok(summary)
+}
+
+// Applies (unless `dry_run`) and clears whatever's accumulated in `batch`,
+// marking `summary.applied` once at least one batch has actually been
+// written - called both at each `IMPORT_BATCH_SIZE` boundary and, with
+// whatever's left over, on cancellation or end of file.
+fn apply_batch(
+    store: &Arc<TabsStore>,
+    batch: &mut Vec<(TabsRecord, ServerTimestamp)>,
+    dry_run: bool,
+    summary: &mut ImportSummary,
+) -> ApiResult<()> {
+    if batch.is_empty() || dry_run {
+        batch.clear();
+        return Ok(());
+    }
+    store
+        .storage
+        .lock()
+        .unwrap()
+        .replace_remote_tabs(std::mem::take(batch))?;
+    summary.applied = true;
+    Ok(())
+}
+
+// Parses and validates a single NDJSON line, returning the `TabsRecord` it
+// describes (ready for `TabsStorage::replace_remote_tabs`) or a
+// human-readable reason it was rejected. The dump format is whatever
+// `export::run_export` writes - a `ClientRemoteTabs` per line - since
+// that's the richest view of a client this crate ever has in memory; we
+// only keep what `replace_remote_tabs` actually persists.
+fn validate_line(line: &str) -> std::result::Result<(TabsRecord, ServerTimestamp), String> {
+    let crt: ClientRemoteTabs =
+        serde_json::from_str(line).map_err(|e| format!("invalid JSON: {e}"))?;
+    if crt.client_id.is_empty() {
+        return Err("record has an empty client_id".to_string());
+    }
+    for tab in &crt.remote_tabs {
+        for url in &tab.url_history {
+            if let Err(e) = url::Url::parse(url) {
+                return Err(format!(
+                    "tab {:?} has an invalid URL {url:?}: {e}",
+                    tab.title
+                ));
+            }
+        }
+    }
+    let last_modified = ServerTimestamp::from_millis(crt.last_modified.max(0));
+    Ok((crt.to_record(), last_modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::RemoteTab;
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingCallback {
+        records: Mutex<Vec<(u64, Option<String>)>>,
+        completed: Mutex<Option<ApiResult<ImportSummary>>>,
+    }
+
+    impl ImportProgressCallback for RecordingCallback {
+        fn on_record(&self, line_number: u64, error: Option<String>) {
+            self.records.lock().unwrap().push((line_number, error));
+        }
+
+        fn on_complete(&self, result: ApiResult<ImportSummary>) {
+            *self.completed.lock().unwrap() = Some(result);
+        }
+    }
+
+    fn write_dump(path: &std::path::Path, lines: &[String]) {
+        let file = File::create(path).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        for line in lines {
+            writeln!(encoder, "{line}").unwrap();
+        }
+        encoder.finish().unwrap();
+    }
+
+    fn valid_line(client_id: &str) -> String {
+        serde_json::to_string(&ClientRemoteTabs {
+            client_id: client_id.to_string(),
+            client_name: "Device".to_string(),
+            device_type: Default::default(),
+            last_modified: 1000,
+            capabilities: Vec::new(),
+            os: None,
+            form_factor: None,
+            remote_tabs: vec![RemoteTab {
+                title: "example".to_string(),
+                url_history: vec!["https://example.com/".to_string()],
+                ..Default::default()
+            }],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_import_applies_valid_records_and_reports_invalid_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(TabsStore::new(dir.path().join("test_import.db")));
+        let dump_path = dir.path().join("dump.ndjson.gz");
+        write_dump(
+            &dump_path,
+            &[valid_line("device-1"), "not valid json".to_string()],
+        );
+
+        let callback = Arc::new(RecordingCallback::default());
+        let handle = TabsImportHandle::start(store.clone(), dump_path, false, callback.clone());
+        drop(handle); // joins the background thread
+
+        let summary = match callback.completed.lock().unwrap().take().unwrap() {
+            Ok(summary) => summary,
+            Err(e) => panic!("import failed: {e}"),
+        };
+        assert_eq!(summary.records_valid, 1);
+        assert_eq!(summary.records_invalid, 1);
+        assert!(summary.applied);
+        assert!(summary.completed);
+
+        let records = callback.records.lock().unwrap();
+        assert_eq!(records[0], (1, None));
+        assert!(records[1].1.is_some());
+
+        let imported = store.get_all(true);
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].client_id, "device-1");
+    }
+
+    #[test]
+    fn test_dry_run_validates_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(TabsStore::new(dir.path().join("test_import_dry_run.db")));
+        let dump_path = dir.path().join("dump.ndjson.gz");
+        write_dump(&dump_path, &[valid_line("device-1")]);
+
+        let callback = Arc::new(RecordingCallback::default());
+        let handle = TabsImportHandle::start(store.clone(), dump_path, true, callback.clone());
+        drop(handle);
+
+        let summary = callback
+            .completed
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap()
+            .expect("dry run shouldn't fail");
+        assert_eq!(summary.records_valid, 1);
+        assert!(!summary.applied);
+        assert!(store.get_all(true).is_empty());
+    }
+
+    #[test]
+    fn test_cancel_stops_import_early() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(TabsStore::new(dir.path().join("test_import_cancel.db")));
+        let dump_path = dir.path().join("dump.ndjson.gz");
+        write_dump(&dump_path, &[valid_line("device-1")]);
+
+        let callback = Arc::new(RecordingCallback::default());
+        let handle = TabsImportHandle::start(store, dump_path, false, callback.clone());
+        handle.cancel();
+        drop(handle);
+
+        let summary = callback
+            .completed
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap()
+            .expect("cancellation isn't an error");
+        assert!(!summary.completed);
+    }
+}
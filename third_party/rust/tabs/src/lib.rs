@@ -4,13 +4,33 @@
 
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
+// A number of storage helpers (sync counters, pending-ack bookkeeping, the
+// legacy-envelope fixup, etc.) only have a caller in `sync::engine`/
+// `sync::bridge`, which are themselves gated behind `full-sync` - see that
+// feature's doc comment in Cargo.toml. Rather than threading
+// `#[cfg(feature = "full-sync")]` through every such method individually,
+// just suppress the resulting dead-code warnings when the feature is off.
+#![cfg_attr(not(feature = "full-sync"), allow(dead_code))]
 
 #[macro_use]
 pub mod error;
+#[cfg(feature = "debug-tools")]
+mod debug_tools;
+#[cfg(feature = "debug-tools")]
+mod export;
+mod glean;
+#[cfg(feature = "debug-tools")]
+mod import;
+mod log_redact;
+mod metrics;
+mod observer;
+mod policy;
 mod schema;
 mod storage;
 mod store;
 mod sync;
+#[cfg(feature = "test-support")]
+mod test_fixtures;
 
 uniffi::include_scaffolding!("tabs");
 
@@ -28,12 +48,31 @@ impl UniffiCustomTypeConverter for TabsGuid {
     }
 }
 
-pub use crate::storage::{ClientRemoteTabs, RemoteTabRecord, TabsDeviceType};
+#[cfg(feature = "debug-tools")]
+pub use crate::export::{ExportProgressCallback, TabsExportHandle};
+#[cfg(feature = "debug-tools")]
+pub use crate::import::{ImportProgressCallback, ImportSummary, TabsImportHandle};
+pub use crate::metrics::MetricsReportingCallback;
+pub use crate::observer::{InvalidateReason, TabsChangeSummary, TabsSyncObserver};
+pub use crate::policy::UploadPolicyCheck;
+pub use crate::storage::{
+    ClientRemoteTabs, ComponentInfo, ConsistencyFinding, DeviceWithUrl, RemoteTabRecord,
+    StorageFootprint, TabPickupStat, TabsDeviceType, TabsHistorySnapshot,
+};
 pub use crate::store::TabsStore;
+#[cfg(feature = "test-support")]
+pub use crate::test_fixtures::TestFixtureCallback;
 pub use error::{ApiResult, Error, Result, TabsApiError};
+#[cfg(feature = "glean-metrics")]
+pub use glean::GleanMetricsObserver;
 use sync15::DeviceType;
 
+#[cfg(feature = "full-sync")]
 pub use crate::sync::engine::get_registered_sync_engine;
+#[cfg(feature = "full-sync")]
+pub use crate::sync::engine::{AbortSyncReason, EngineConfigState};
 
+#[cfg(feature = "full-sync")]
 pub use crate::sync::bridge::TabsBridgedEngine;
+#[cfg(feature = "full-sync")]
 pub use crate::sync::engine::TabsEngine;
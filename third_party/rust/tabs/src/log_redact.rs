@@ -0,0 +1,73 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Log lines must never contain raw tab titles/URLs by default - they're
+//! user browsing data. This gives the sync code a single place to decide
+//! whether to log the real value or an elided stand-in, controlled by the
+//! `sensitive-logging` engine pref (see `TabsStorage::sensitive_logging_enabled`)
+//! so a developer debugging locally can opt back in.
+
+use crate::storage::ClientRemoteTabs;
+
+/// Returns a `Debug`-able summary of `crt` suitable for a log line: the real
+/// value when `reveal` is true, otherwise just counts and an elided client id.
+pub(crate) fn redact_client_remote_tabs(crt: &ClientRemoteTabs, reveal: bool) -> String {
+    if reveal {
+        format!("{:?}", crt)
+    } else {
+        format!(
+            "ClientRemoteTabs {{ client_id: {}, tabs: {} }}",
+            elide(&crt.client_id),
+            crt.remote_tabs.len()
+        )
+    }
+}
+
+// Keeps enough of the value to be useful when correlating log lines, without
+// leaking the whole thing.
+fn elide(s: &str) -> String {
+    if s.len() <= 8 {
+        "<redacted>".to_string()
+    } else {
+        format!("{}…<redacted>", &s[..8])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::RemoteTab;
+    use sync15::DeviceType;
+
+    fn sample() -> ClientRemoteTabs {
+        ClientRemoteTabs {
+            client_id: "abcdefghijklmnop".to_string(),
+            client_name: "my phone".to_string(),
+            device_type: DeviceType::Mobile,
+            last_modified: 0,
+            capabilities: vec![],
+            os: None,
+            form_factor: None,
+            remote_tabs: vec![RemoteTab {
+                title: "secret title".to_string(),
+                url_history: vec!["https://example.com/secret".to_string()],
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_redacted_by_default() {
+        let out = redact_client_remote_tabs(&sample(), false);
+        assert!(!out.contains("secret"));
+        assert!(!out.contains("my phone"));
+        assert!(out.contains("tabs: 1"));
+    }
+
+    #[test]
+    fn test_revealed_when_sensitive_logging_enabled() {
+        let out = redact_client_remote_tabs(&sample(), true);
+        assert!(out.contains("secret title"));
+    }
+}
@@ -0,0 +1,75 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Telemetry and about:sync both want a periodic snapshot of the performance
+//! counters (storage footprint, length cap violations, etc) without polling
+//! the individual getters themselves. Rather than this crate owning a timer or
+//! a "main thread" concept it doesn't otherwise have, `TabsStore` drives a
+//! dedicated background thread that wakes up on the requested interval and
+//! hands the embedder a compact JSON blob - see `TabsStore::start_metrics_reporting`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::store::TabsStore;
+
+/// Implemented by the embedder to receive the periodic snapshots dispatched by
+/// `TabsStore::start_metrics_reporting`. Called from the dedicated reporting
+/// thread, never from the caller's thread - implementations that need to reach
+/// a specific thread (eg JS's main thread) are responsible for redispatching.
+pub trait MetricsReportingCallback: Send + Sync {
+    /// `snapshot_json` is the same shape produced by `TabsStore::metrics_snapshot_json`.
+    fn on_metrics_snapshot(&self, snapshot_json: String);
+}
+
+/// Owns the background thread started by `start_metrics_reporting`. Stopping
+/// reporting (explicitly, via `stop_metrics_reporting`, or implicitly by
+/// dropping the store) signals the thread and joins it, so no snapshot fires
+/// after the handle is gone.
+pub(crate) struct MetricsReportingHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsReportingHandle {
+    pub(crate) fn start(
+        store: Arc<TabsStore>,
+        interval_ms: u32,
+        callback: Arc<dyn MetricsReportingCallback>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        // A zero interval would otherwise spin the thread hot - treat it the same
+        // as "as often as we reasonably can".
+        let interval = Duration::from_millis(interval_ms.max(1) as u64);
+        let thread = std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::SeqCst) {
+                std::thread::sleep(interval);
+                if thread_stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                callback.on_metrics_snapshot(store.metrics_snapshot_json());
+            }
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for MetricsReportingHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // The thread sleeps in `interval`-sized chunks, so this can block the
+        // dropping thread for up to one interval - acceptable since dropping the
+        // store (or calling `stop_metrics_reporting`) is already a "shutting
+        // down" style operation, not a hot path.
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,86 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! In-tree Rust consumers (eg future tab recommendation code) want sync
+//! lifecycle notifications without going through an XPCOM observer. This
+//! exposes a plain Rust trait the embedder can register on `TabsStore` -
+//! callbacks run on whatever thread drives sync, so implementations should be
+//! quick and hand any real work off to their own queue.
+
+use std::sync::Arc;
+
+/// What changed as a result of a sync `apply()`, passed to
+/// `TabsSyncObserver::on_apply` so consumers don't need to re-derive it themselves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TabsChangeSummary {
+    /// How many remote clients' tabs were staged by this sync.
+    pub incoming_tabs: u32,
+    /// How many records we uploaded (0 or 1 - tabs only ever uploads our own).
+    pub outgoing_tabs: u32,
+    /// IDs of remote clients whose tabs changed this sync - staged with new
+    /// tabs, or tombstoned and dropped from the mirror. Lets a consumer (eg
+    /// the Synced Tabs UI) refresh just the affected clients instead of
+    /// re-reading the whole mirror on every `on_apply`.
+    pub changed_client_ids: Vec<String>,
+}
+
+/// A "close this tab" request targeting the local client, forwarded to
+/// `TabsSyncObserver::on_close_tab_requested` as it's staged rather than
+/// persisted - see `crate::storage::TabsStorage::queue_close_remote_tab_command`
+/// for the outgoing side of the same feature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CloseTabRequest {
+    /// The command's own ID, for acking back via `TabsStore::request_close_remote_tab`'s
+    /// caller queuing `TabsStorage::queue_command_ack(command_id, "done")`
+    /// once it's honored.
+    pub command_id: String,
+    /// The remote client that issued this request.
+    pub sender_client_id: String,
+    pub url: String,
+}
+
+/// Why `on_invalidate` fired, for a consumer that wants to log or react
+/// differently depending on the cause without needing two separate handlers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvalidateReason {
+    /// Sync state (and the local mirror) was reset, eg on account disconnect
+    /// or a node reassignment.
+    Reset,
+    /// The local mirror was wiped.
+    Wipe,
+}
+
+pub trait TabsSyncObserver: Send + Sync {
+    /// Called after a sync `apply()` completes successfully.
+    fn on_apply(&self, summary: TabsChangeSummary);
+    /// Called whenever any cached copy of remote tabs a consumer is holding
+    /// (the awesomebar index, an OS integration snapshot, etc) is now stale and
+    /// should be thrown away wholesale, rather than patched. Fired in addition
+    /// to the more specific `on_wipe`/`on_reset` below. Defaulted to a no-op so
+    /// existing observers don't need to change to keep compiling.
+    fn on_invalidate(&self, reason: InvalidateReason) {
+        let _ = reason;
+    }
+    /// Called after the local mirror is wiped (eg on account disconnect).
+    fn on_wipe(&self);
+    /// Called after sync state (last sync time, sync IDs) is reset.
+    fn on_reset(&self);
+    /// Called every time a sync attempt is refused because the database has
+    /// been degraded by repeated corruption - see
+    /// `TabsEngine::require_not_degraded`. Fires on every such attempt, not
+    /// just the first, since the condition persists until a human intervenes.
+    /// Defaulted to a no-op so existing observers don't need to change to
+    /// keep compiling.
+    fn on_degraded(&self) {}
+    /// Called during `stage_incoming` for each incoming close-tab command
+    /// targeting the local client - honoring it (closing the tab, then
+    /// queuing an ack via `TabsStorage::queue_command_ack`) is the embedder's
+    /// job, not something this crate does on its behalf. Defaulted to a
+    /// no-op so existing observers don't need to change to keep compiling.
+    fn on_close_tab_requested(&self, request: CloseTabRequest) {
+        let _ = request;
+    }
+}
+
+pub(crate) type TabsSyncObserverHandle = std::sync::RwLock<Option<Arc<dyn TabsSyncObserver>>>;
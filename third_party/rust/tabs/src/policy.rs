@@ -0,0 +1,18 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Lets the embedder gate outgoing tab uploads behind its own policy (eg a
+//! mobile "Wi-Fi only" sync setting) without this crate needing to know
+//! anything about networks or connectivity itself.
+
+use std::sync::Arc;
+
+/// Consulted once per `apply()`, just before we'd otherwise build the outgoing
+/// envelope. Defaults to always allowing uploads if none is registered.
+pub trait UploadPolicyCheck: Send + Sync {
+    /// Returns whether we're currently allowed to upload the local tab payload.
+    fn should_upload(&self) -> bool;
+}
+
+pub(crate) type UploadPolicyCheckHandle = std::sync::RwLock<Option<Arc<dyn UploadPolicyCheck>>>;
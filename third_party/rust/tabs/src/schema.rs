@@ -5,6 +5,15 @@
 // Tabs is a bit special - it's a trivial SQL schema and is only used as a persistent
 // cache, and the semantics of the "tabs" collection means there's no need for
 // syncChangeCounter/syncStatus nor a mirror etc.
+//
+// Schema changes go through `sql_support::open_database`'s `user_version`-based
+// migration runner (the same one `webext_storage` uses), driven by
+// `TabsMigrationLogic` below: bump `END_VERSION`, add an `upgrade_from_vN`
+// match arm for the old version, and add a `CREATE_*_SCHEMA_SQL`/`ALTER_*_SQL`
+// constant for whatever's new. `init` only ever has to build the latest
+// schema from scratch; `upgrade_from` carries every existing DB forward one
+// version at a time. See the `tests` module below for how to exercise an
+// upgrade from an older on-disk schema.
 
 use rusqlite::{Connection, Transaction};
 use sql_support::{
@@ -16,11 +25,15 @@ use sql_support::{
 
 // The record is the TabsRecord struct in json and this module doesn't need to deserialize, so we just
 // store each client as its own row.
+// `format` tags which codec encoded `record` (see `storage::encode_record`), so
+// a row written by an older or newer build than the one reading it is never
+// misinterpreted - 0 means JSON, which every build can always read.
 const CREATE_SCHEMA_SQL: &str = "
     CREATE TABLE IF NOT EXISTS tabs (
         guid            TEXT NOT NULL PRIMARY KEY,
         record          TEXT NOT NULL,
-        last_modified   INTEGER NOT NULL
+        last_modified   INTEGER NOT NULL,
+        format          INTEGER NOT NULL DEFAULT 0
     );
 ";
 
@@ -31,6 +44,138 @@ const CREATE_META_TABLE_SQL: &str = "
     )
 ";
 
+// A ring-buffer of historical snapshots per client, for features like "tabs from
+// yesterday". Rows are trimmed back down to `SNAPSHOT_HISTORY_LIMIT` per `guid`
+// whenever a new snapshot is recorded (see `record_history_snapshot`), so this
+// table stays bounded regardless of how often a client syncs.
+const CREATE_HISTORY_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS tabs_history (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid            TEXT NOT NULL,
+        record          TEXT NOT NULL,
+        last_modified   INTEGER NOT NULL,
+        format          INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS idx_tabs_history_guid ON tabs_history(guid, last_modified);
+";
+
+// Per-tab "dismissed" overlay - lets a user hide a single remote tab from the
+// panel without hiding the whole device (see `TabsStorage::dismiss_remote_tab`).
+// `record_last_modified` pins the dismissal to the client record it was made
+// against, so it's automatically dropped once that record is replaced by a
+// newer one - see `TabsStorage::replace_remote_tabs_inner`.
+const CREATE_DISMISSED_TABS_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS dismissed_tabs (
+        client_id             TEXT NOT NULL,
+        url_hash              TEXT NOT NULL,
+        record_last_modified  INTEGER NOT NULL,
+        PRIMARY KEY (client_id, url_hash)
+    );
+";
+
+// Acks we owe for commands targeted at us (eg a remote tab-close request),
+// queued here until the next outgoing record can include them - see
+// `TabsStorage::queue_command_ack`. Cleared once `set_uploaded` confirms the
+// record reached the server, so an ack is only ever uploaded once.
+const CREATE_PENDING_COMMAND_ACKS_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS pending_command_acks (
+        command_id  TEXT NOT NULL PRIMARY KEY,
+        status      TEXT NOT NULL,
+        created_at  INTEGER NOT NULL
+    );
+";
+
+// Acks we've ingested for commands *we* sent, purely so
+// `TabsStorage::record_incoming_ack` can tell a replayed ack (eg the sending
+// client's record reappearing in a later `since` window) from a new one and
+// avoid re-processing it.
+const CREATE_ACKED_COMMANDS_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS acked_commands (
+        command_id     TEXT NOT NULL PRIMARY KEY,
+        status         TEXT NOT NULL,
+        last_modified  INTEGER NOT NULL
+    );
+";
+
+// Caches the canonicalized form of every URL we've seen, so
+// `TabsStorage::canonical_url_for` doesn't need to recompute it on every
+// lookup - see `TabsStorage::backfill_canonical_urls_chunked` for how
+// existing rows get populated here after canonicalization was introduced.
+const CREATE_CANONICAL_URLS_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS canonical_urls (
+        url            TEXT NOT NULL PRIMARY KEY,
+        canonical_url  TEXT NOT NULL
+    );
+";
+
+// Opt-in, privacy-preserving aggregate counts of remote-tab hosts, for product
+// analytics (top hosts by remote-tab count) without ever storing a URL or
+// cleartext hostname - see `TabsStorage::record_host_stats`. `host_hash` is a
+// truncated hash, same spirit as `tab_pickup_stats.url_hash`, just computed by
+// this crate itself rather than the caller, since the whole point is that the
+// hostname never leaves this process in the clear.
+const CREATE_HOST_STATS_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS host_stats (
+        host_hash  TEXT NOT NULL PRIMARY KEY,
+        tab_count  INTEGER NOT NULL
+    );
+";
+
+// Send Tab ("display URI") items received from another client, kept as a flat
+// inbox rather than folded into `tabs`/`tabs_history` - these aren't a
+// client's browsing-tab state, just a single URL+title someone chose to send.
+// Trimmed back down to `RECEIVED_TABS_RETENTION_LIMIT` whenever a new one is
+// recorded - see `TabsStorage::store_received_tab`.
+const CREATE_RECEIVED_TABS_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS received_tabs (
+        id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+        sender_client_id   TEXT,
+        url                TEXT NOT NULL,
+        title              TEXT NOT NULL,
+        received_at        INTEGER NOT NULL,
+        opened_at          INTEGER
+    );
+";
+
+// Outgoing "close this tab" requests we've originated but haven't yet
+// uploaded - see `TabsStorage::queue_close_remote_tab_command`. Cleared once
+// `set_uploaded` confirms the record (and therefore the command) reached the
+// server, the same as `pending_command_acks`. The *receiving* end of a close
+// request doesn't get a table of its own: `TabsEngine::stage_incoming`
+// forwards it straight to `TabsSyncObserver::on_close_tab_requested` rather
+// than persisting it, since honoring it (and queuing the ack back via
+// `queue_command_ack`) is the embedder's job, not something to replay if it
+// missed a notification.
+const CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS pending_close_commands (
+        command_id        TEXT NOT NULL PRIMARY KEY,
+        target_client_id  TEXT NOT NULL,
+        url               TEXT NOT NULL,
+        created_at        INTEGER NOT NULL
+    );
+";
+
+const ALTER_TABS_FORMAT_SQL: &str =
+    "ALTER TABLE tabs ADD COLUMN format INTEGER NOT NULL DEFAULT 0;";
+const ALTER_TABS_HISTORY_FORMAT_SQL: &str =
+    "ALTER TABLE tabs_history ADD COLUMN format INTEGER NOT NULL DEFAULT 0;";
+
+// "Tab pickup" onboarding metrics - counts how many times a remote tab was
+// opened locally, for product to measure whether users who receive remote
+// tabs actually act on them. Keyed by `apply_generation` (see
+// `APPLY_GENERATION_KEY`) rather than a timestamp, so counts naturally bucket
+// by sync rather than needing a separate cleanup pass. `url_hash` is exactly
+// that - a hash, never the URL itself - see `TabsStorage::record_tab_opened`.
+const CREATE_TAB_PICKUP_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS tab_pickup_stats (
+        client_id        TEXT NOT NULL,
+        url_hash         TEXT NOT NULL,
+        apply_generation INTEGER NOT NULL,
+        opened_count     INTEGER NOT NULL,
+        PRIMARY KEY (client_id, url_hash, apply_generation)
+    );
+";
+
 pub(crate) static LAST_SYNC_META_KEY: &str = "last_sync_time";
 pub(crate) static GLOBAL_SYNCID_META_KEY: &str = "global_sync_id";
 pub(crate) static COLLECTION_SYNCID_META_KEY: &str = "tabs_sync_id";
@@ -38,14 +183,72 @@ pub(crate) static COLLECTION_SYNCID_META_KEY: &str = "tabs_sync_id";
 // of connected clients when syncing, however getting the list of tabs could be called at anytime
 // so we store it so we can translate from the tabs sync record ID to the FxA device id for the client
 pub(crate) static REMOTE_CLIENTS_KEY: &str = "remote_clients";
+// A single-row "journal" of the most recently set local tabs, written synchronously
+// whenever `update_local_state` is called (if the DB already exists) so a crash
+// between JS collecting tabs and the next write can recover the pending snapshot.
+pub(crate) static LOCAL_TABS_JOURNAL_KEY: &str = "local_tabs_journal";
+// Rows deleted since we last ran an incremental vacuum - compared against
+// `DEFAULT_VACUUM_ROW_THRESHOLD` (or a caller-supplied override) to decide when it's
+// worth reclaiming pages.
+pub(crate) static ROWS_DELETED_SINCE_VACUUM_KEY: &str = "rows_deleted_since_vacuum";
+// Count of titles/URLs we've had to truncate or drop for exceeding the configured
+// length caps (either a remote client's tabs on ingestion, or our own on upload).
+pub(crate) static LENGTH_CAP_VIOLATIONS_KEY: &str = "length_cap_violations";
+// How many devices we've indexed so far in a chunked filter-index rebuild, so an
+// interrupted rebuild resumes rather than restarting. Cleared once it completes.
+pub(crate) static FILTER_INDEX_REBUILD_OFFSET_KEY: &str = "filter_index_rebuild_offset";
+// How many devices we've backfilled so far in a chunked canonical-URL backfill,
+// so an interrupted backfill resumes rather than restarting. Cleared once it
+// completes - see `TabsStorage::backfill_canonical_urls_chunked`.
+pub(crate) static CANONICAL_URL_BACKFILL_OFFSET_KEY: &str = "canonical_url_backfill_offset";
+// Absolute ms-since-epoch timestamp until which we suppress further writes after
+// hitting SQLITE_FULL - see `TabsStorage::note_disk_full`. Absent/expired means
+// writes are allowed.
+pub(crate) static DISK_FULL_BACKOFF_UNTIL_KEY: &str = "disk_full_backoff_until";
+// JSON array of client GUIDs the user has locally chosen to hide from the Synced
+// Tabs list (eg an old laptop they don't want to disconnect) - see
+// `TabsStorage::set_client_hidden`. This is purely a local display preference and
+// is never synced.
+pub(crate) static HIDDEN_CLIENTS_KEY: &str = "hidden_clients";
+// Set when `set_last_sync` sees the server's timestamp jump backwards by more
+// than a transient amount, suggesting the collection may have been reset or
+// restored server-side - consulted (and cleared) by `get_collection_request` to
+// force a full mirror refresh on the next sync rather than trusting `since`.
+pub(crate) static FORCE_MIRROR_REFRESH_KEY: &str = "force_mirror_refresh";
+// Count of clients `remove_stale_clients` has purged for exceeding the TTL,
+// accumulated across calls so a sync that repeatedly stages incoming records
+// without ever reaching `apply` still leaves evidence behind - see
+// `TabsEngine::on_sync_started`.
+pub(crate) static STALE_ROWS_PURGED_KEY: &str = "stale_rows_purged";
+// Count of incoming tabs we've had to drop because a single sync session
+// exceeded `MAX_STAGED_TABS_PER_SESSION` - see `TabsEngine::stage_incoming`.
+pub(crate) static STAGE_CAP_VIOLATIONS_KEY: &str = "stage_cap_violations";
+// Whether syncing has been paused via `TabsBridgedEngine::pause` - consulted
+// (but never mutated) by `TabsBridgedEngineAdaptor::sync_started`. Local write
+// APIs ignore this entirely.
+pub(crate) static SYNC_PAUSED_KEY: &str = "sync_paused";
+// Monotonically-incrementing counter bumped once per completed `apply()` -
+// tags each row `record_tab_opened` writes to `tab_pickup_stats`, so pickup
+// counts can be correlated with the sync that offered the tab.
+pub(crate) static APPLY_GENERATION_KEY: &str = "apply_generation";
+// Count of our own tabs `trim_tabs_length` has dropped from an outgoing
+// payload for exceeding `MAX_PAYLOAD_SIZE` - see
+// `TabsStorage::prepare_local_tabs_for_upload`.
+pub(crate) static OUTGOING_TABS_TRIMMED_KEY: &str = "outgoing_tabs_trimmed";
 
 pub struct TabsMigrationLogic;
 
+// Exposed separately from the trait impl below so callers (eg `get_component_info`)
+// don't need `ConnectionInitializer` in scope just to read the version.
+pub(crate) fn schema_version() -> i64 {
+    TabsMigrationLogic::END_VERSION as i64
+}
+
 impl MigrationLogic for TabsMigrationLogic {
     const NAME: &'static str = "tabs storage db";
-    const END_VERSION: u32 = 2;
+    const END_VERSION: u32 = 11;
 
-    fn prepare(&self, conn: &Connection, _db_empty: bool) -> MigrationResult<()> {
+    fn prepare(&self, conn: &Connection, db_empty: bool) -> MigrationResult<()> {
         let initial_pragmas = "
             -- We don't care about temp tables being persisted to disk.
             PRAGMA temp_store = 2;
@@ -55,6 +258,12 @@ impl MigrationLogic for TabsMigrationLogic {
             PRAGMA foreign_keys = ON;
         ";
         conn.execute_batch(initial_pragmas)?;
+        if db_empty {
+            // auto_vacuum can only be changed before any tables are created, so this is
+            // a no-op on an existing DB. It lets us run cheap `PRAGMA incremental_vacuum`
+            // calls instead of a full (blocking) VACUUM.
+            conn.execute_batch("PRAGMA auto_vacuum=INCREMENTAL;")?;
+        }
         // This is where we'd define our sql functions if we had any!
         conn.set_prepared_statement_cache_capacity(128);
         Ok(())
@@ -62,13 +271,34 @@ impl MigrationLogic for TabsMigrationLogic {
 
     fn init(&self, db: &Transaction<'_>) -> MigrationResult<()> {
         log::debug!("Creating schemas");
-        db.execute_all(&[CREATE_SCHEMA_SQL, CREATE_META_TABLE_SQL])?;
+        db.execute_all(&[
+            CREATE_SCHEMA_SQL,
+            CREATE_META_TABLE_SQL,
+            CREATE_HISTORY_SCHEMA_SQL,
+            CREATE_TAB_PICKUP_SCHEMA_SQL,
+            CREATE_DISMISSED_TABS_SCHEMA_SQL,
+            CREATE_PENDING_COMMAND_ACKS_SCHEMA_SQL,
+            CREATE_ACKED_COMMANDS_SCHEMA_SQL,
+            CREATE_CANONICAL_URLS_SCHEMA_SQL,
+            CREATE_HOST_STATS_SCHEMA_SQL,
+            CREATE_RECEIVED_TABS_SCHEMA_SQL,
+            CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL,
+        ])?;
         Ok(())
     }
 
     fn upgrade_from(&self, db: &Transaction<'_>, version: u32) -> MigrationResult<()> {
         match version {
             1 => upgrade_from_v1(db),
+            2 => upgrade_from_v2(db),
+            3 => upgrade_from_v3(db),
+            4 => upgrade_from_v4(db),
+            5 => upgrade_from_v5(db),
+            6 => upgrade_from_v6(db),
+            7 => upgrade_from_v7(db),
+            8 => upgrade_from_v8(db),
+            9 => upgrade_from_v9(db),
+            10 => upgrade_from_v10(db),
             _ => Err(MigrationError::IncompatibleVersion(version)),
         }
     }
@@ -78,8 +308,154 @@ fn upgrade_from_v1(db: &Connection) -> MigrationResult<()> {
     // The previous version stored the entire payload in one row
     // and cleared on each sync -- it's fine to just drop it
     db.execute_batch("DROP TABLE tabs;")?;
-    // Recreate the world
-    db.execute_all(&[CREATE_SCHEMA_SQL, CREATE_META_TABLE_SQL])?;
+    // Recreate the world, straight to the current (v11) schema - there's no
+    // pre-existing `tabs_history`/`tab_pickup_stats`/`dismissed_tabs`/
+    // `pending_command_acks`/`acked_commands`/`canonical_urls`/`host_stats`/
+    // `received_tabs`/`pending_close_commands` tables to worry about yet, and
+    // the fresh `tabs` table already has `format`, so there's nothing left
+    // for `upgrade_from_v2`/`upgrade_from_v3`/`upgrade_from_v4`/
+    // `upgrade_from_v5`/`upgrade_from_v6`/`upgrade_from_v7`/
+    // `upgrade_from_v8`/`upgrade_from_v9`/`upgrade_from_v10` to add.
+    db.execute_all(&[
+        CREATE_SCHEMA_SQL,
+        CREATE_META_TABLE_SQL,
+        CREATE_HISTORY_SCHEMA_SQL,
+        CREATE_TAB_PICKUP_SCHEMA_SQL,
+        CREATE_DISMISSED_TABS_SCHEMA_SQL,
+        CREATE_PENDING_COMMAND_ACKS_SCHEMA_SQL,
+        CREATE_ACKED_COMMANDS_SCHEMA_SQL,
+        CREATE_CANONICAL_URLS_SCHEMA_SQL,
+        CREATE_HOST_STATS_SCHEMA_SQL,
+        CREATE_RECEIVED_TABS_SCHEMA_SQL,
+        CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL,
+    ])?;
+    Ok(())
+}
+
+fn upgrade_from_v2(db: &Connection) -> MigrationResult<()> {
+    // `tabs_history` doesn't exist yet at v2 and is created fresh with
+    // `format` already present; `tabs` pre-dates it though, so it's the only
+    // one that needs the column added here. `tab_pickup_stats`/`dismissed_tabs`/
+    // `pending_command_acks`/`acked_commands`/`canonical_urls`/`host_stats`/
+    // `received_tabs`/`pending_close_commands` don't exist at this version
+    // either, so create them fresh too.
+    db.execute_batch(CREATE_HISTORY_SCHEMA_SQL)?;
+    db.execute_batch(ALTER_TABS_FORMAT_SQL)?;
+    db.execute_batch(CREATE_TAB_PICKUP_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_DISMISSED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_COMMAND_ACKS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_ACKED_COMMANDS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_CANONICAL_URLS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_HOST_STATS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_RECEIVED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL)?;
+    Ok(())
+}
+
+fn upgrade_from_v3(db: &Connection) -> MigrationResult<()> {
+    // Both tables already exist at v3, pre-dating `format` - add it to both.
+    // `tab_pickup_stats`/`dismissed_tabs`/`pending_command_acks`/
+    // `acked_commands`/`canonical_urls`/`host_stats`/`received_tabs`/
+    // `pending_close_commands` don't exist yet, so create them fresh.
+    db.execute_batch(ALTER_TABS_FORMAT_SQL)?;
+    db.execute_batch(ALTER_TABS_HISTORY_FORMAT_SQL)?;
+    db.execute_batch(CREATE_TAB_PICKUP_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_DISMISSED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_COMMAND_ACKS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_ACKED_COMMANDS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_CANONICAL_URLS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_HOST_STATS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_RECEIVED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL)?;
+    Ok(())
+}
+
+fn upgrade_from_v4(db: &Connection) -> MigrationResult<()> {
+    // `tabs`/`tabs_history` are already fully up to date at v4 - only
+    // `tab_pickup_stats`/`dismissed_tabs`/`pending_command_acks`/
+    // `acked_commands`/`canonical_urls`/`host_stats`/`received_tabs`/
+    // `pending_close_commands` are new since then.
+    db.execute_batch(CREATE_TAB_PICKUP_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_DISMISSED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_COMMAND_ACKS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_ACKED_COMMANDS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_CANONICAL_URLS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_HOST_STATS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_RECEIVED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL)?;
+    Ok(())
+}
+
+fn upgrade_from_v5(db: &Connection) -> MigrationResult<()> {
+    // Everything else is already up to date at v5 - `dismissed_tabs`,
+    // `pending_command_acks`, `acked_commands`, `canonical_urls`,
+    // `host_stats`, `received_tabs`, and `pending_close_commands` are new
+    // since then.
+    db.execute_batch(CREATE_DISMISSED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_COMMAND_ACKS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_ACKED_COMMANDS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_CANONICAL_URLS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_HOST_STATS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_RECEIVED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL)?;
+    Ok(())
+}
+
+fn upgrade_from_v6(db: &Connection) -> MigrationResult<()> {
+    // Everything else is already up to date at v6 - `pending_command_acks`,
+    // `acked_commands`, `canonical_urls`, `host_stats`, `received_tabs`, and
+    // `pending_close_commands` are new since then.
+    db.execute_batch(CREATE_PENDING_COMMAND_ACKS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_ACKED_COMMANDS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_CANONICAL_URLS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_HOST_STATS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_RECEIVED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL)?;
+    Ok(())
+}
+
+fn upgrade_from_v7(db: &Connection) -> MigrationResult<()> {
+    // Everything else is already up to date at v7 - `canonical_urls`,
+    // `host_stats`, `received_tabs`, and `pending_close_commands` are new
+    // since then. Existing `canonical_urls` rows are backfilled lazily on
+    // first lookup (see `TabsStorage::canonical_url_for`) or eagerly via
+    // `TabsStorage::backfill_canonical_urls_chunked` - this migration only
+    // needs to create the (initially empty) tables.
+    db.execute_batch(CREATE_CANONICAL_URLS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_HOST_STATS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_RECEIVED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL)?;
+    Ok(())
+}
+
+fn upgrade_from_v8(db: &Connection) -> MigrationResult<()> {
+    // Everything else is already up to date at v8 - `host_stats`,
+    // `received_tabs`, and `pending_close_commands` are new since then. All
+    // start out empty regardless: `host_stats`'s aggregation pass is opt-in
+    // (see `TabsStorage::record_host_stats`), and neither `received_tabs`
+    // nor `pending_close_commands` have anything to backfill from - only
+    // ever run forward from whatever happens next.
+    db.execute_batch(CREATE_HOST_STATS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_RECEIVED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL)?;
+    Ok(())
+}
+
+fn upgrade_from_v9(db: &Connection) -> MigrationResult<()> {
+    // Everything else is already up to date at v9 - `received_tabs` and
+    // `pending_close_commands` are new since then. Both start out empty;
+    // there's nothing to backfill an inbox of previously-received tabs or
+    // not-yet-uploaded close requests from.
+    db.execute_batch(CREATE_RECEIVED_TABS_SCHEMA_SQL)?;
+    db.execute_batch(CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL)?;
+    Ok(())
+}
+
+fn upgrade_from_v10(db: &Connection) -> MigrationResult<()> {
+    // Everything else is already up to date at v10 - only
+    // `pending_close_commands` is new since then. It starts out empty;
+    // there's nothing to backfill a not-yet-uploaded close request from.
+    db.execute_batch(CREATE_PENDING_CLOSE_COMMANDS_SCHEMA_SQL)?;
     Ok(())
 }
 
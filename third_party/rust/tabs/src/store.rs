@@ -2,40 +2,763 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::storage::{ClientRemoteTabs, RemoteTab, TabsStorage};
+use crate::error::{ApiResult, Error};
+use crate::metrics::{MetricsReportingCallback, MetricsReportingHandle};
+use crate::observer::{TabsSyncObserver, TabsSyncObserverHandle};
+use crate::policy::{UploadPolicyCheck, UploadPolicyCheckHandle};
+use crate::storage::{
+    get_component_info, ClientRemoteTabs, CommandAck, ComponentInfo, ConsistencyFinding,
+    DedupedRemoteTab, DeviceWithUrl, HostStat, MaintenanceReport, ReceivedTab, RemoteTab,
+    StorageFootprint, TabPickupStat, TabsHealth, TabsHistorySnapshot, TabsStorage,
+};
+use error_support::handle_error;
+use interrupt_support::{Interruptee, NeverInterrupts};
 use std::path::Path;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 pub struct TabsStore {
+    // Note that every call through this `Mutex` is still serialized against every
+    // other, regardless of what SQLite itself allows - the chunked-transaction
+    // apply in `TabsStorage::replace_remote_tabs` (see its comment) only benefits
+    // a reader on a *separate* connection to the same file, eg a diagnostic tool
+    // opened directly against the on-disk DB, not a second call made through
+    // this same `TabsStore`.
     pub storage: Mutex<TabsStorage>,
+    #[cfg(feature = "glean-metrics")]
+    pub(crate) glean_observer: crate::glean::GleanObserverHandle,
+    pub(crate) sync_observer: TabsSyncObserverHandle,
+    pub(crate) upload_policy_check: UploadPolicyCheckHandle,
+    metrics_reporting: Mutex<Option<MetricsReportingHandle>>,
+    // The last-applied remote mirror, pre-rendered as JSON by `TabsEngine::
+    // stage_incoming` - see `get_cached_remote_tabs_json`. `RwLock` rather
+    // than the `storage` `Mutex` since reading this never needs to block on
+    // (or behind) a real storage operation.
+    cached_remote_tabs: std::sync::RwLock<Option<String>>,
+    // Flipped by `interrupt_rebuild_search_index` and checked by
+    // `rebuild_search_index` between chunks - same "embedder flips a flag
+    // from another thread, the in-flight call notices at its next check"
+    // shape as `TabsEngine`'s `aborted`, rather than an `Interruptee`
+    // directly, since an embedder needs to signal this from a different
+    // thread than the one blocked inside `rebuild_search_index`.
+    rebuild_search_index_cancelled: AtomicBool,
+}
+
+/// Adapts `rebuild_search_index_cancelled` to the `Interruptee` that
+/// `rebuild_filter_index_chunked` expects, mirroring how `abort_sync`'s
+/// `aborted` flag is checked via `require_not_aborted`.
+struct CancelFlagInterruptee<'a>(&'a AtomicBool);
+
+impl Interruptee for CancelFlagInterruptee<'_> {
+    fn was_interrupted(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+lazy_static::lazy_static! {
+    // Backs `TabsStore::get_or_create_shared` - see its doc comment. The
+    // `Mutex` is what actually makes init idempotent: two threads racing to
+    // create the shared store for the first time still only construct one,
+    // since the loser sees `shared` already populated once it acquires the lock.
+    static ref SHARED_STORE: Mutex<Option<Arc<TabsStore>>> = Mutex::new(None);
 }
 
 impl TabsStore {
+    /// Like `TabsStorage::new`, this is cheap and never opens the database -
+    /// it just wires up the in-memory bookkeeping (observers, policy checks,
+    /// metrics reporting state) around a lazily-opened `TabsStorage`. An
+    /// embedder can construct (or `get_or_create_shared`) a store up front,
+    /// on whatever thread is convenient, without paying SQLite's open cost
+    /// until the first real operation actually needs a connection.
+    ///
+    /// Unlike `webext_storage_bridge`'s `StorageSyncArea`, which is
+    /// registered as an XPCOM component before Desktop knows the profile
+    /// directory and so needs a separate `mozIConfigurableExtensionStorageArea
+    /// ::configure` step (backed by `LazyStore::configure`, erroring if
+    /// called twice or skipped) to supply `db_path` later from JS, `tabs`
+    /// has no XPCOM registration to be constructed ahead of - it's a UniFFI
+    /// component that mobile embedders (Fenix, Firefox iOS) construct
+    /// directly, already knowing the profile path, so `db_path` is simply a
+    /// constructor argument here rather than a deferred, fallible
+    /// reconfiguration. There's likewise no `NS_New...`-style C-callable
+    /// constructor to resolve a profile-relative filename (eg
+    /// `synced-tabs.db`) on our behalf - joining `db_path` onto the profile
+    /// directory is the embedder's job, same as every other path it already
+    /// hands this crate (`new_with_mem_path` aside).
     pub fn new(db_path: impl AsRef<Path>) -> Self {
         Self {
             storage: Mutex::new(TabsStorage::new(db_path)),
+            #[cfg(feature = "glean-metrics")]
+            glean_observer: Default::default(),
+            sync_observer: Default::default(),
+            upload_policy_check: Default::default(),
+            metrics_reporting: Default::default(),
+            cached_remote_tabs: Default::default(),
+            rebuild_search_index_cancelled: Default::default(),
         }
     }
 
     pub fn new_with_mem_path(db_path: &str) -> Self {
         Self {
             storage: Mutex::new(TabsStorage::new_with_mem_path(db_path)),
+            #[cfg(feature = "glean-metrics")]
+            glean_observer: Default::default(),
+            sync_observer: Default::default(),
+            upload_policy_check: Default::default(),
+            metrics_reporting: Default::default(),
+            cached_remote_tabs: Default::default(),
+            rebuild_search_index_cancelled: Default::default(),
         }
     }
 
+    /// Returns the process-wide shared store, creating it against `db_path` the
+    /// first time this is called - every later call, from any thread, returns
+    /// that exact same instance and ignores `db_path`, mirroring an XPCOM
+    /// service's `getService`. Unlike `new`/`new_with_mem_path`, not exposed via
+    /// uniffi: each embedder already has its own idiom for handing out a single
+    /// lazily-constructed instance (eg a lazy getter in JS), so this exists for
+    /// Rust-only consumers that link against this crate directly.
+    pub fn get_or_create_shared(db_path: impl AsRef<Path>) -> Arc<Self> {
+        let mut shared = SHARED_STORE.lock().unwrap();
+        if let Some(store) = &*shared {
+            return Arc::clone(store);
+        }
+        let store = Arc::new(Self::new(db_path));
+        *shared = Some(Arc::clone(&store));
+        store
+    }
+
+    /// Sanity-checks the path this store was constructed with before the
+    /// first real operation tries to open it - see
+    /// `TabsStorage::validate_db_path`. Entirely optional (`new`/
+    /// `new_with_mem_path` stay lazy and never call this themselves), but an
+    /// embedder that calls it right after construction gets a precise,
+    /// typed `TabsApiError::InvalidDatabasePathError` up front instead of a
+    /// confusing raw SQLite error the first time something actually touches
+    /// the DB.
+    #[handle_error(Error)]
+    pub fn validate_db_path(&self) -> ApiResult<()> {
+        Ok(self.storage.lock().unwrap().validate_db_path()?)
+    }
+
+    /// Installs the observer used to mirror sync telemetry to Glean instead of
+    /// (or in addition to) the legacy sync ping.
+    #[cfg(feature = "glean-metrics")]
+    pub fn set_glean_metrics_observer(
+        &self,
+        observer: std::sync::Arc<dyn crate::glean::GleanMetricsObserver>,
+    ) {
+        *self.glean_observer.write().unwrap() = Some(observer);
+    }
+
+    /// Installs an observer for sync lifecycle events (apply/wipe/reset), for
+    /// in-tree Rust consumers that want to react to sync without going through
+    /// an XPCOM observer - eg to drive tab recommendations.
+    pub fn set_sync_observer(&self, observer: Arc<dyn TabsSyncObserver>) {
+        *self.sync_observer.write().unwrap() = Some(observer);
+    }
+
+    /// Installs a policy check consulted before each `apply()` generates outgoing
+    /// tabs - eg a mobile "Wi-Fi only" sync setting. When it denies an upload,
+    /// `apply()` returns incoming-only results and the local payload stays
+    /// pending for the next sync that's allowed to upload.
+    pub fn set_upload_policy_check(&self, check: Arc<dyn UploadPolicyCheck>) {
+        *self.upload_policy_check.write().unwrap() = Some(check);
+    }
+
+    /// The entry point an embedder (eg Firefox Desktop) uses to hand this
+    /// store its currently-open tabs - `url_history`, `title`, `icon` and
+    /// `last_used` per `RemoteTabRecord` - so `apply()`'s next outgoing
+    /// payload reflects them. Replaces whatever was previously recorded;
+    /// see `set_local_tabs_for_window` for embedders that need to merge
+    /// more than one window's tabs instead.
     pub fn set_local_tabs(&self, local_state: Vec<RemoteTab>) {
         self.storage.lock().unwrap().update_local_state(local_state);
     }
 
+    /// Like `set_local_tabs`, but for embedders with more than one window:
+    /// `window_id`'s tabs are merged by union with every other window's most
+    /// recent snapshot rather than replacing them outright, so one window
+    /// reporting its tabs doesn't erase what another window already reported.
+    /// `timestamp` should be whenever `window_id`'s tabs last changed - an
+    /// update older than what's already on hand for that window is ignored.
+    pub fn set_local_tabs_for_window(
+        &self,
+        window_id: String,
+        timestamp: i64,
+        local_state: Vec<RemoteTab>,
+    ) {
+        self.storage.lock().unwrap().update_local_state_for_window(
+            &window_id,
+            timestamp,
+            local_state,
+        );
+    }
+
+    /// Recovers a local tabs snapshot journaled before a crash, if any. Should be
+    /// called once, early in startup.
+    #[handle_error(Error)]
+    pub fn recover_journaled_local_tabs(&self) -> ApiResult<Vec<RemoteTab>> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .recover_journaled_local_tabs()?
+            .unwrap_or_default())
+    }
+
     // like remote_tabs, but serves the uniffi layer
-    pub fn get_all(&self) -> Vec<ClientRemoteTabs> {
-        match self.remote_tabs() {
+    pub fn get_all(&self, include_hidden: bool) -> Vec<ClientRemoteTabs> {
+        match self.remote_tabs(include_hidden) {
             Some(list) => list,
             None => vec![],
         }
     }
 
-    pub fn remote_tabs(&self) -> Option<Vec<ClientRemoteTabs>> {
-        self.storage.lock().unwrap().get_remote_tabs()
+    pub fn remote_tabs(&self, include_hidden: bool) -> Option<Vec<ClientRemoteTabs>> {
+        self.storage.lock().unwrap().get_remote_tabs(include_hidden)
+    }
+
+    /// Like `get_all`, but devices and their tabs are pre-sorted by recency for
+    /// the Synced Tabs panel, saving it a re-sort/re-group pass over a
+    /// potentially large list.
+    pub fn get_for_display(&self, include_hidden: bool) -> Vec<ClientRemoteTabs> {
+        self.storage
+            .lock()
+            .unwrap()
+            .get_for_display(include_hidden)
+            .unwrap_or_default()
+    }
+
+    /// A synchronous fast path for callers (eg the Synced Tabs sidebar's
+    /// first paint) that want *something* to show immediately rather than
+    /// waiting out a task-queue round-trip to `get_for_display` - the JSON
+    /// `TabsEngine::stage_incoming` cached the last time it wrote the
+    /// mirror, or `None` if no sync has ever applied. Unlike `get_for_display`
+    /// this never touches `storage` (so it can't block behind an in-flight
+    /// write) and can go stale if a sync applies between this call and
+    /// whatever reads it - callers that need the authoritative, up-to-date
+    /// list should use `get_for_display` instead.
+    pub fn get_cached_remote_tabs_json(&self) -> Option<String> {
+        self.cached_remote_tabs.read().unwrap().clone()
+    }
+
+    /// Called by `TabsEngine::stage_incoming` after a successful write -
+    /// see `get_cached_remote_tabs_json`.
+    pub(crate) fn set_cached_remote_tabs_json(&self, json: Option<String>) {
+        *self.cached_remote_tabs.write().unwrap() = json;
+    }
+
+    /// Like `get_all`, but only `client_id`'s tabs - for callers that already
+    /// know which device they want rather than the whole mirror. Null if
+    /// `client_id` isn't present in the mirror.
+    pub fn get_remote_tabs_for_client(&self, client_id: String) -> Option<ClientRemoteTabs> {
+        self.storage
+            .lock()
+            .unwrap()
+            .get_remote_tabs_for_client(&client_id)
+    }
+
+    /// Hides (or un-hides) `guid` from the Synced Tabs list without disconnecting
+    /// it - eg for an old device the user doesn't want cluttering the list but
+    /// isn't ready to forget entirely. Purely a local display preference.
+    #[handle_error(Error)]
+    pub fn set_client_hidden(&self, guid: String, hidden: bool) -> ApiResult<()> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .set_client_hidden(&guid, hidden)?)
+    }
+
+    /// Drops `client_id`'s remote tabs record from the local mirror
+    /// immediately - see `TabsStorage::delete_remote_client` for why this
+    /// can't also delete the record from the server. Intended for an
+    /// embedder that already knows (eg from the FxA device manager) that a
+    /// device has disconnected and wants it gone from the Synced Tabs list
+    /// right away, rather than waiting out `remove_stale_clients`'s TTL.
+    #[handle_error(Error)]
+    pub fn remove_remote_client(&self, client_id: String) -> ApiResult<()> {
+        self.storage
+            .lock()
+            .unwrap()
+            .delete_remote_client(&client_id)?;
+        self.set_cached_remote_tabs_json(None);
+        Ok(())
+    }
+
+    /// Removes every locally-stored trace of `host` (and its subdomains) from
+    /// the synced tabs mirror and history - intended for `ClearDataService` to
+    /// call when the user (or a site) asks Firefox to forget a site. Local tabs
+    /// aren't touched here since they're just a live snapshot the tab manager
+    /// re-reports on its own.
+    #[handle_error(Error)]
+    pub fn delete_by_host(&self, host: String) -> ApiResult<()> {
+        self.storage.lock().unwrap().delete_by_host(&host)?;
+        Ok(())
+    }
+
+    /// Records that a remote tab from `client_id` was opened locally, for the
+    /// "tab pickup" onboarding metric - see `get_tab_pickup_stats`. `url_hash`
+    /// must already be hashed by the caller; the cleartext URL is never seen
+    /// here.
+    #[handle_error(Error)]
+    pub fn record_tab_opened(&self, client_id: String, url_hash: String) -> ApiResult<()> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .record_tab_opened(&client_id, &url_hash)?)
+    }
+
+    /// All recorded "tab pickup" counters - see `record_tab_opened`.
+    #[handle_error(Error)]
+    pub fn get_tab_pickup_stats(&self) -> ApiResult<Vec<TabPickupStat>> {
+        Ok(self.storage.lock().unwrap().get_tab_pickup_stats()?)
+    }
+
+    /// Dismisses a single remote tab from the Synced Tabs panel without
+    /// hiding the whole device - see `set_client_hidden` for that, and
+    /// `get_dismissed_tab_hashes` for how dismissals are queried back.
+    /// `url_hash` must already be hashed by the caller, same convention as
+    /// `record_tab_opened`.
+    #[handle_error(Error)]
+    pub fn dismiss_remote_tab(&self, client_id: String, url_hash: String) -> ApiResult<()> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .dismiss_remote_tab(&client_id, &url_hash)?)
+    }
+
+    /// The still-dismissed url hashes for `client_id` - see
+    /// `dismiss_remote_tab`. A dismissal stops being returned here once
+    /// `client_id`'s record is replaced by a newer one.
+    #[handle_error(Error)]
+    pub fn get_dismissed_tab_hashes(&self, client_id: String) -> ApiResult<Vec<String>> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .get_dismissed_tab_hashes(&client_id)?)
+    }
+
+    /// Queues an ack for a command we've processed (eg a remote tab-close
+    /// request we've honored locally), to go out with our next outgoing
+    /// record - see `get_acked_commands` for the other direction.
+    #[handle_error(Error)]
+    pub fn queue_command_ack(&self, command_id: String, status: String) -> ApiResult<()> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .queue_command_ack(&command_id, &status)?)
+    }
+
+    /// The commands we've sent that have since been acked by their target -
+    /// see `queue_command_ack` for the other direction.
+    #[handle_error(Error)]
+    pub fn get_acked_commands(&self) -> ApiResult<Vec<CommandAck>> {
+        Ok(self.storage.lock().unwrap().get_acked_commands()?)
+    }
+
+    /// Requests that `client_id` close the tab at `url`, to go out with our
+    /// next outgoing record - see `TabsSyncObserver::on_close_tab_requested`
+    /// for the receiving end of the same feature, and `queue_command_ack` for
+    /// how `client_id` acks it back once honored.
+    #[handle_error(Error)]
+    pub fn request_close_remote_tab(&self, client_id: String, url: String) -> ApiResult<()> {
+        self.storage
+            .lock()
+            .unwrap()
+            .queue_close_remote_tab_command(&client_id, &url)?;
+        Ok(())
+    }
+
+    /// Records a Send Tab item received from another client, for display in
+    /// a local "received tabs" inbox - see `ReceivedTab`/
+    /// `get_unopened_received_tabs`. `sender_client_id` is the fxa_device_id
+    /// of the sending client, if known.
+    #[handle_error(Error)]
+    pub fn store_received_tab(
+        &self,
+        sender_client_id: Option<String>,
+        url: String,
+        title: String,
+    ) -> ApiResult<()> {
+        Ok(self.storage.lock().unwrap().store_received_tab(
+            sender_client_id.as_deref(),
+            &url,
+            &title,
+        )?)
+    }
+
+    /// The received tabs the user hasn't opened yet - see
+    /// `store_received_tab`/`mark_received_tab_opened`.
+    #[handle_error(Error)]
+    pub fn get_unopened_received_tabs(&self) -> ApiResult<Vec<ReceivedTab>> {
+        Ok(self.storage.lock().unwrap().get_unopened_received_tabs()?)
+    }
+
+    /// Marks a received tab as opened, so it stops showing up in
+    /// `get_unopened_received_tabs`. A no-op if `id` doesn't exist.
+    #[handle_error(Error)]
+    pub fn mark_received_tab_opened(&self, id: i64) -> ApiResult<()> {
+        Ok(self.storage.lock().unwrap().mark_received_tab_opened(id)?)
+    }
+
+    // Diagnostic entry-point for about:sync - recomputes the expected mirror state
+    // and reports anything that looks inconsistent.
+    #[handle_error(Error)]
+    pub fn verify_consistency(&self) -> ApiResult<Vec<ConsistencyFinding>> {
+        Ok(self.storage.lock().unwrap().verify_consistency()?)
+    }
+
+    /// Returns the current on-disk footprint, and runs an incremental vacuum first if
+    /// enough rows have been deleted since the last one.
+    #[handle_error(Error)]
+    pub fn get_storage_footprint(&self) -> ApiResult<StorageFootprint> {
+        let mut storage = self.storage.lock().unwrap();
+        storage.run_incremental_vacuum_if_due(None)?;
+        Ok(storage.get_storage_footprint()?)
+    }
+
+    /// Runs a full maintenance pass (integrity check, incremental vacuum,
+    /// WAL checkpoint) - heavier than `get_storage_footprint`'s opportunistic
+    /// vacuum, so callers should schedule this occasionally (eg from an
+    /// idle-daily observer) rather than on every sync.
+    #[handle_error(Error)]
+    pub fn run_maintenance(&self) -> ApiResult<MaintenanceReport> {
+        Ok(self.storage.lock().unwrap().run_maintenance()?)
+    }
+
+    /// Opt-in, privacy-preserving aggregate remote-tab counts by (hashed)
+    /// host, for product analytics like "top hosts by remote-tab count" -
+    /// see `TabsStorage::record_host_stats`. Always empty unless the
+    /// "host-stats-opt-in" engine pref has been set to `"true"`.
+    #[handle_error(Error)]
+    pub fn get_host_stats(&self) -> ApiResult<Vec<HostStat>> {
+        Ok(self.storage.lock().unwrap().get_host_stats()?)
+    }
+
+    /// Disables (or re-enables) the auto-tuned `mmap_size` read optimization
+    /// applied whenever the DB is opened - for low-memory devices where
+    /// memory-mapped I/O competes with the embedder's own budget rather than
+    /// helping. Takes effect the next time the DB is (re)opened.
+    pub fn set_mmap_disabled(&self, disabled: bool) {
+        self.storage.lock().unwrap().set_mmap_disabled(disabled);
+    }
+
+    /// Sets a generic, JSON-valued engine pref (eg the dry-run flag, exclusions, log
+    /// level) - `json_value` must be valid JSON.
+    #[handle_error(Error)]
+    pub fn set_engine_pref(&self, key: String, json_value: String) -> ApiResult<()> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .set_engine_pref(&key, &json_value)?)
+    }
+
+    #[handle_error(Error)]
+    pub fn get_engine_pref(&self, key: String) -> ApiResult<Option<String>> {
+        Ok(self.storage.lock().unwrap().get_engine_pref(&key)?)
+    }
+
+    /// Returns the number of times we've had to truncate or drop an overly long
+    /// title/URL, for about:support diagnostics.
+    #[handle_error(Error)]
+    pub fn get_length_cap_violations(&self) -> ApiResult<i64> {
+        Ok(self.storage.lock().unwrap().get_length_cap_violations()?)
+    }
+
+    /// Returns the number of clients we've purged for being stale (see
+    /// `TabsStorage::remove_stale_clients`), for about:support diagnostics - a
+    /// high count can be a sign a sync is stuck repeatedly staging incoming
+    /// records without ever applying them.
+    #[handle_error(Error)]
+    pub fn get_stale_rows_purged(&self) -> ApiResult<i64> {
+        Ok(self.storage.lock().unwrap().get_stale_rows_purged()?)
+    }
+
+    /// Returns the number of incoming tabs we've had to drop for exceeding the
+    /// per-sync-session staging cap, for about:support diagnostics.
+    #[handle_error(Error)]
+    pub fn get_stage_cap_violations(&self) -> ApiResult<i64> {
+        Ok(self.storage.lock().unwrap().get_stage_cap_violations()?)
+    }
+
+    /// Returns version/build info for this instance of the vendored `tabs` crate,
+    /// for diagnosing bridge/crate mismatches from about:support.
+    pub fn get_component_info(&self) -> ComponentInfo {
+        get_component_info()
+    }
+
+    /// Coarse, machine-readable health status for about:support's sync
+    /// section - see `TabsStorage::get_health` for exactly how it's derived.
+    #[handle_error(Error)]
+    pub fn get_health(&self) -> ApiResult<TabsHealth> {
+        Ok(self.storage.lock().unwrap().get_health()?)
+    }
+
+    /// QA/support's "make this component forget everything" button - see
+    /// `TabsStorage::factory_reset` for exactly what that covers. The database
+    /// file and this `TabsStore` instance are both left in place; only their
+    /// contents are cleared.
+    #[handle_error(Error)]
+    pub fn factory_reset(&self) -> ApiResult<()> {
+        Ok(self.storage.lock().unwrap().factory_reset()?)
+    }
+
+    /// Rebuilds the search index used by `filter_remote_tabs` in chunks of
+    /// `chunk_size` devices, so enabling search on an existing large mirror
+    /// doesn't block the caller for the whole rebuild. Safe to call repeatedly -
+    /// progress is persisted, so a rebuild interrupted by eg a crash, or by
+    /// `interrupt_rebuild_search_index`, resumes where it left off. Returns
+    /// whether the rebuild completed.
+    #[handle_error(Error)]
+    pub fn rebuild_search_index(&self, chunk_size: u32) -> ApiResult<bool> {
+        self.rebuild_search_index_cancelled
+            .store(false, Ordering::SeqCst);
+        Ok(self.storage.lock().unwrap().rebuild_filter_index_chunked(
+            &CancelFlagInterruptee(&self.rebuild_search_index_cancelled),
+            chunk_size as usize,
+        )?)
+    }
+
+    /// Requests that an in-flight `rebuild_search_index` stop after its
+    /// current chunk, rather than waiting for the whole mirror. Safe to call
+    /// from a different thread than the one running the rebuild - same
+    /// "only ever flips a flag the in-flight call notices at its next check"
+    /// shape as `TabsEngine::abort_sync`. A no-op if no rebuild is running;
+    /// the next `rebuild_search_index` call clears the flag itself, so this
+    /// doesn't need a matching "resume" call.
+    pub fn interrupt_rebuild_search_index(&self) {
+        self.rebuild_search_index_cancelled
+            .store(true, Ordering::SeqCst);
+    }
+
+    /// Backfills the canonical-URL cache used by `get_devices_with_url` across
+    /// the whole mirror, in chunks of `chunk_size` devices, so enabling
+    /// canonicalization on an existing large mirror doesn't block the caller
+    /// for the whole backfill. Safe to call repeatedly - progress is
+    /// persisted, so a backfill interrupted by eg a crash resumes where it
+    /// left off. Returns whether the backfill completed - see
+    /// `rebuild_search_index` for the analogous search-index job.
+    #[handle_error(Error)]
+    pub fn backfill_canonical_urls(&self, chunk_size: u32) -> ApiResult<bool> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .backfill_canonical_urls_chunked(&NeverInterrupts, chunk_size as usize)?)
+    }
+
+    /// Returns every remote tab whose title or URL contains `needle`, for the
+    /// awesomebar. Cheap to call on every keystroke - the underlying index is only
+    /// rebuilt when the mirror actually changes.
+    #[handle_error(Error)]
+    pub fn filter_remote_tabs(&self, needle: String) -> ApiResult<Vec<RemoteTab>> {
+        Ok(self.storage.lock().unwrap().filter_remote_tabs(&needle)?)
+    }
+
+    /// Like `filter_remote_tabs`, but ranked by most-recently-used first and
+    /// capped to `limit` - for the awesomebar's "tabs from other devices"
+    /// suggestions, which want a short, relevance-ordered list rather than
+    /// shipping every match to JS for it to sort and trim itself.
+    ///
+    /// With `dedupe`, matches whose current URL is identical across clients
+    /// collapse into one entry (the most-recently-used copy), annotated with
+    /// every client that had it open - see `DedupedRemoteTab`.
+    #[handle_error(Error)]
+    pub fn query_remote_tabs(
+        &self,
+        needle: String,
+        limit: u32,
+        dedupe: bool,
+    ) -> ApiResult<Vec<DedupedRemoteTab>> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .query_remote_tabs(&needle, limit, dedupe)?)
+    }
+
+    /// Returns every device with `url` somewhere in its synced tab history, for a
+    /// "this page is already open on your other device" indicator. Reuses the
+    /// same index `filter_remote_tabs` does, so it's fast enough to call from
+    /// page-action code on demand.
+    #[handle_error(Error)]
+    pub fn get_devices_with_url(&self, url: String) -> ApiResult<Vec<DeviceWithUrl>> {
+        Ok(self.storage.lock().unwrap().get_devices_with_url(&url)?)
+    }
+
+    /// Returns `client_id`'s historical tab snapshots, newest first, for features
+    /// like "tabs from yesterday". Bounded to the last handful of snapshots per
+    /// client - see `TabsHistorySnapshot`.
+    #[handle_error(Error)]
+    pub fn get_snapshot_history(&self, client_id: String) -> ApiResult<Vec<TabsHistorySnapshot>> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .get_snapshot_history(&client_id)?)
+    }
+
+    /// Returns `client_id`'s closest tab snapshot at or before `timestamp_millis`,
+    /// for a "tabs from yesterday" style view - `None` if no snapshot that old exists.
+    #[handle_error(Error)]
+    pub fn get_client_tabs_at(
+        &self,
+        client_id: String,
+        timestamp_millis: i64,
+    ) -> ApiResult<Option<TabsHistorySnapshot>> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .get_snapshot_at(&client_id, timestamp_millis)?)
+    }
+
+    /// Starts a background thread that dispatches a compact JSON metrics snapshot
+    /// to `callback` every `interval_ms`, for telemetry and about:sync to consume
+    /// without polling the individual getters. Replaces any previously-started
+    /// reporting. Reporting stops automatically when this store is dropped, or
+    /// explicitly via `stop_metrics_reporting`.
+    pub fn start_metrics_reporting(
+        self: Arc<Self>,
+        interval_ms: u32,
+        callback: Box<dyn MetricsReportingCallback>,
+    ) {
+        let handle = MetricsReportingHandle::start(self.clone(), interval_ms, callback.into());
+        *self.metrics_reporting.lock().unwrap() = Some(handle);
+    }
+
+    /// Stops a previously-started `start_metrics_reporting`. A no-op if reporting
+    /// isn't currently running.
+    pub fn stop_metrics_reporting(&self) {
+        self.metrics_reporting.lock().unwrap().take();
+    }
+
+    /// Closes the underlying database connection (if one is open) and marks
+    /// this store as torn down, for an embedder shutting down cleanly (eg
+    /// Firefox Desktop quitting) rather than relying on the connection
+    /// closing whenever this store happens to be dropped. Every operation
+    /// below this point - on this store or any other handle sharing the same
+    /// `TabsStorage` - fails with `TabsApiError::AlreadyTornDown` afterwards;
+    /// there's no way back from this short of constructing a new store.
+    pub fn shutdown(&self) {
+        self.stop_metrics_reporting();
+        self.storage.lock().unwrap().close();
+    }
+
+    /// Runs a named debug command against this store, for interactive use from
+    /// xpcshell or the browser console - not something a real caller should ever
+    /// depend on. See `debug_tools::execute` for the supported command names.
+    ///
+    /// Unlike the rest of this interface, not exposed via uniffi - like
+    /// `glean-metrics`, `debug-tools` is a Rust-only feature, so enabling it
+    /// changes what this crate exports, not what the embedder sees.
+    #[cfg(feature = "debug-tools")]
+    #[handle_error(Error)]
+    pub fn execute_debug_command(&self, name: String, args_json: String) -> ApiResult<String> {
+        Ok(crate::debug_tools::execute(
+            &mut self.storage.lock().unwrap(),
+            &name,
+            &args_json,
+        )?)
+    }
+
+    /// Applies a named, deterministic fixture to this store, for xpcshell
+    /// suites exercising the bridge APIs above - see `test_fixtures` for the
+    /// fixture catalog.
+    ///
+    /// Unlike `execute_debug_command`, this *is* exposed via uniffi:
+    /// `debug-tools` only changes what a Rust caller inside this crate can
+    /// reach, but xpcshell needs to call this from JS.
+    #[cfg(feature = "test-support")]
+    #[handle_error(Error)]
+    pub fn load_test_fixture(
+        &self,
+        name: String,
+        callback: Box<dyn crate::test_fixtures::TestFixtureCallback>,
+    ) -> ApiResult<()> {
+        Ok(crate::test_fixtures::load(
+            &mut self.storage.lock().unwrap(),
+            &name,
+            callback.as_ref(),
+        )?)
+    }
+
+    /// Streams every remote tab record to a gzip-compressed NDJSON file at
+    /// `path`, in chunks, on a dedicated background thread - for a support-
+    /// requested dump of a mirror too large to build in memory the way
+    /// `execute_debug_command`'s `dump-clients` does. Progress and completion
+    /// (or cancellation, via the returned handle) are reported to `callback`.
+    /// Like the rest of this family, not exposed via uniffi.
+    #[cfg(feature = "debug-tools")]
+    pub fn export_to_file(
+        self: Arc<Self>,
+        path: String,
+        callback: Box<dyn crate::export::ExportProgressCallback>,
+    ) -> crate::export::TabsExportHandle {
+        crate::export::TabsExportHandle::start(self, path.into(), callback.into())
+    }
+
+    /// Reads back a dump written by `export_to_file` and applies it to the
+    /// local mirror, in batches, on a dedicated background thread - so
+    /// support can reproduce a user's reported state locally. Each record is
+    /// validated independently and reported via `callback` before anything
+    /// is written; pass `dry_run` to validate the whole dump without
+    /// applying it. Like the rest of this family, not exposed via uniffi.
+    #[cfg(feature = "debug-tools")]
+    pub fn import_from_file(
+        self: Arc<Self>,
+        path: String,
+        dry_run: bool,
+        callback: Box<dyn crate::import::ImportProgressCallback>,
+    ) -> crate::import::TabsImportHandle {
+        crate::import::TabsImportHandle::start(self, path.into(), dry_run, callback.into())
+    }
+
+    /// Builds the JSON snapshot dispatched by `start_metrics_reporting`, from the
+    /// same counters exposed individually via `get_storage_footprint`,
+    /// `get_length_cap_violations`, `get_stale_rows_purged` and
+    /// `get_stage_cap_violations`. Failures reading a counter are reported as
+    /// `null` rather than skipping the whole snapshot.
+    pub(crate) fn metrics_snapshot_json(&self) -> String {
+        let mut storage = self.storage.lock().unwrap();
+        let footprint = storage.get_storage_footprint().ok();
+        let length_cap_violations = storage.get_length_cap_violations().ok();
+        let stale_rows_purged = storage.get_stale_rows_purged().ok();
+        let stage_cap_violations = storage.get_stage_cap_violations().ok();
+        serde_json::json!({
+            "db_size_bytes": footprint.as_ref().map(|f| f.db_size_bytes),
+            "rows_deleted_since_vacuum": footprint.as_ref().map(|f| f.rows_deleted_since_vacuum),
+            "length_cap_violations": length_cap_violations,
+            "stale_rows_purged": stale_rows_purged,
+            "stage_cap_violations": stage_cap_violations,
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_or_create_shared_is_idempotent() {
+        let mem_path = "file:test-get-or-create-shared?mode=memory&cache=shared";
+        let first = TabsStore::get_or_create_shared(mem_path);
+        // A second call - even with a different path - returns the exact same
+        // instance rather than opening a second database.
+        let second = TabsStore::get_or_create_shared("a-different-path-that-is-ignored");
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // And it's genuinely shared: a write through one handle is visible
+        // through the other.
+        first.set_local_tabs(vec![]);
+        assert!(second.recover_journaled_local_tabs().is_ok());
     }
 }
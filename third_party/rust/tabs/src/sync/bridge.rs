@@ -2,7 +2,8 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::sync::engine::TabsEngine;
+use crate::error::ApiResult;
+use crate::sync::engine::{AbortSyncReason, EngineConfigState, TabsEngine};
 use crate::TabsStore;
 use anyhow::Result;
 use std::sync::Arc;
@@ -11,12 +12,42 @@ use sync15::engine::{BridgedEngine, BridgedEngineAdaptor};
 use sync15::ServerTimestamp;
 use sync_guid::Guid as SyncGuid;
 
+// A generous upper bound on a single incoming envelope's serialized JSON
+// size. The server enforces its own record size limit (see
+// `sync15::client::request`), but we don't have that config available here,
+// and a single malformed or hostile envelope many times that size would
+// otherwise make us allocate and copy an unbounded amount of memory before
+// `serde_json` ever gets a chance to reject it as invalid.
+const MAX_INCOMING_ENVELOPE_BYTES: usize = 2 * 1024 * 1024;
+
+// Shared by `sync_started`/`store_incoming`/`apply` below - each wraps a
+// `BridgedEngine` method whose `anyhow::Result` could be hiding one of our
+// own typed errors (eg `SyncPaused`, `NotConfigured`) that we want reported
+// distinctly, rather than losing it to the generic `anyhow::Error ->
+// TabsApiError` conversion every other failure goes through.
+fn downcast_bridge_error(e: anyhow::Error) -> crate::TabsApiError {
+    match e.downcast::<crate::Error>() {
+        Ok(err) => error_support::convert_log_report_error(err),
+        Err(e) => e.into(),
+    }
+}
+
 impl TabsStore {
     // Returns a bridged sync engine for Desktop for this store.
+    //
+    // Note there's no `RefCell`-guarded lazy store here to worry about: unlike
+    // some other app-services XPCOM bridges, this one is built directly from
+    // an owned `Arc<TabsStore>` the embedder already holds, and every method
+    // below that needs the engine goes through the `engine: Arc<TabsEngine>`
+    // field captured here rather than re-borrowing anything - so there's no
+    // borrow left held across a `Mutex::lock()` for a re-entrant callback to
+    // trip over.
     pub fn bridged_engine(self: Arc<Self>) -> Arc<TabsBridgedEngine> {
-        let engine = TabsEngine::new(self);
-        let bridged_engine = TabsBridgedEngineAdaptor { engine };
-        Arc::new(TabsBridgedEngine::new(Box::new(bridged_engine)))
+        let engine = Arc::new(TabsEngine::new(self));
+        let bridged_engine = TabsBridgedEngineAdaptor {
+            engine: engine.clone(),
+        };
+        Arc::new(TabsBridgedEngine::new(Box::new(bridged_engine), engine))
     }
 }
 
@@ -24,9 +55,22 @@ impl TabsStore {
 /// `storage.sync` store work with Desktop's Sync implementation.
 /// Conceptually it's very similar to our SyncEngine and there's a BridgedEngineAdaptor
 /// trait we can implement to get a `BridgedEngine` from a `SyncEngine`, so that's
-/// what we do. See also #2841, which will finally unify them completely.
+/// what we do.
+///
+/// Unlike `webext_storage_bridge`, which adapts its `golden_gate::BridgedEngine`
+/// on top of a separately-constructed sync engine, we get `BridgedEngine` for
+/// free over `TabsEngine` via `sync15`'s own `BridgedEngineAdaptor` blanket
+/// impl - so `bridged_engine()` below builds exactly one `TabsEngine` per call
+/// and shares that same instance between this adaptor and the
+/// `TabsBridgedEngine` it backs, rather than juggling two engine objects with
+/// state (`last_sync`, `local_id`) that could drift apart.
 struct TabsBridgedEngineAdaptor {
-    engine: TabsEngine,
+    // An `Arc` (rather than owning the engine outright) so `TabsBridgedEngine`
+    // can also hold a handle to it directly - see `TabsBridgedEngine::abort_sync`,
+    // which needs to call `TabsEngine::abort_sync` without going through the
+    // `BridgedEngine` trait object, since that trait is shared with every
+    // other sync15 engine and isn't the place for a tabs-only cancellation hook.
+    engine: Arc<TabsEngine>,
 }
 
 impl BridgedEngineAdaptor for TabsBridgedEngineAdaptor {
@@ -39,19 +83,58 @@ impl BridgedEngineAdaptor for TabsBridgedEngineAdaptor {
             .set_last_sync(ServerTimestamp::from_millis(last_sync_millis))
     }
 
+    // Called once per sync, always before `store_incoming` - our hook for the
+    // bridged (Desktop) path to reset the per-session staging cap and purge
+    // stale clients, the same as `prepare_for_sync` does for the
+    // sync-manager-registered path. See `TabsEngine::on_sync_started`.
+    //
+    // Also where we honour `pause()`: rather than silently skip the rest of
+    // the sync, we return a typed `Error::SyncPaused` the caller can tell
+    // apart from a real failure - see `TabsBridgedEngine::sync_started`.
+    fn sync_started(&self) -> Result<()> {
+        if self.engine.is_sync_paused()? {
+            return Err(crate::Error::SyncPaused.into());
+        }
+        self.engine.on_sync_started()
+    }
+
+    fn pause(&self) -> Result<()> {
+        self.engine.pause()
+    }
+
+    fn resume(&self) -> Result<()> {
+        self.engine.resume()
+    }
+
+    fn is_configured(&self) -> Result<bool> {
+        Ok(matches!(
+            self.engine.debug_state(),
+            EngineConfigState::Configured
+        ))
+    }
+
+    fn is_degraded(&self) -> Result<bool> {
+        Ok(self.engine.is_degraded())
+    }
+
     fn engine(&self) -> &dyn sync15::engine::SyncEngine {
-        &self.engine
+        &*self.engine
     }
 }
 
 // This is for uniffi to expose, and does nothing than delegate back to the trait.
 pub struct TabsBridgedEngine {
     bridge_impl: Box<dyn BridgedEngine>,
+    // See `TabsBridgedEngineAdaptor::engine`.
+    engine: Arc<TabsEngine>,
 }
 
 impl TabsBridgedEngine {
-    pub fn new(bridge_impl: Box<dyn BridgedEngine>) -> Self {
-        Self { bridge_impl }
+    pub fn new(bridge_impl: Box<dyn BridgedEngine>, engine: Arc<TabsEngine>) -> Self {
+        Self {
+            bridge_impl,
+            engine,
+        }
     }
 
     pub fn last_sync(&self) -> Result<i64> {
@@ -66,26 +149,105 @@ impl TabsBridgedEngine {
         self.bridge_impl.sync_id()
     }
 
+    /// Assigns a fresh, random collection sync ID and feeds it into
+    /// `TabsEngine::reset` as `EngineSyncAssociation::Connected` (via
+    /// `sync15`'s `BridgedEngineAdaptor` blanket impl) - so, unlike a plain
+    /// `reset()`/`wipe()`, this leaves us connected under the new ID rather
+    /// than disconnected, matching how full `SyncEngine`s behave when the
+    /// sync manager assigns them a new collection ID.
     pub fn reset_sync_id(&self) -> Result<String> {
         self.bridge_impl.reset_sync_id()
     }
 
+    /// Compares `sync_id` against our current collection sync ID; if they
+    /// differ, resets local Sync state the same way `reset_sync_id` does, but
+    /// connects under `sync_id` (the server's ID) rather than a freshly
+    /// generated one - see `BridgedEngineAdaptor::ensure_current_sync_id`.
     pub fn ensure_current_sync_id(&self, sync_id: &str) -> Result<String> {
         self.bridge_impl.ensure_current_sync_id(sync_id)
     }
 
+    // `client_data` is a JSON-serialized `sync15::ClientData` - the same
+    // struct `TabsEngine::prepare_for_sync` takes on the sync-manager-registered
+    // path. There's no separate `SetLocalClientInfo`-style method for the
+    // bridged path to inject `local_client_id`/`client_name`/`device_type`:
+    // `sync15::engine::BridgedEngineAdaptor`'s blanket `prepare_for_sync`
+    // impl deserializes this same JSON and forwards it straight into
+    // `SyncEngine::prepare_for_sync`, so Desktop already threads its local
+    // client info through exactly the same `ClientData`/`RemoteClient`
+    // plumbing every other registered engine uses - nothing bridge-specific
+    // to add here.
     pub fn prepare_for_sync(&self, client_data: &str) -> Result<()> {
         self.bridge_impl.prepare_for_sync(client_data)
     }
 
-    pub fn sync_started(&self) -> Result<()> {
-        self.bridge_impl.sync_started()
+    pub fn sync_started(&self) -> ApiResult<()> {
+        self.bridge_impl
+            .sync_started()
+            .map_err(downcast_bridge_error)
+    }
+
+    /// Freezes tabs syncing without touching global sync - `sync_started` will
+    /// keep returning `TabsApiError::SyncPaused` until `resume` is called.
+    /// Local write APIs like `set_local_tabs` keep working while paused.
+    pub fn pause(&self) -> Result<()> {
+        self.bridge_impl.pause()
     }
 
-    // Decode the JSON-encoded IncomingBso's that UniFFI passes to us
+    pub fn resume(&self) -> Result<()> {
+        self.bridge_impl.resume()
+    }
+
+    /// Immediately stops whatever `store_incoming`/`apply` call is currently
+    /// in flight - eg the browser going offline mid-sync - rather than
+    /// waiting for it to reach a natural stopping point. `reason` decides
+    /// whether the session's staging progress is dropped or kept for a
+    /// follow-up sync to resume. See `TabsEngine::abort_sync`.
+    pub fn abort_sync(&self, reason: AbortSyncReason) {
+        self.engine.abort_sync(reason)
+    }
+
+    /// The schema version of this crate's local storage, tied to
+    /// `TabsMigrationLogic::END_VERSION` - not a server/protocol version like
+    /// `sync15::client::state`'s `STORAGE_VERSION`. Desktop compares this
+    /// against the server-advertised collection version and wipes/resyncs
+    /// on a mismatch, the same way it already does for our sibling bridged
+    /// engines (logins, addresses, credit cards).
+    pub fn storage_version(&self) -> i64 {
+        crate::schema::schema_version()
+    }
+
+    /// Coarse lifecycle state for about:sync-style diagnostics - see
+    /// `EngineConfigState` for why this crate only distinguishes three phases.
+    pub fn get_debug_state(&self) -> Result<EngineConfigState> {
+        Ok(if self.bridge_impl.is_degraded()? {
+            EngineConfigState::Degraded
+        } else if self.bridge_impl.is_configured()? {
+            EngineConfigState::Configured
+        } else {
+            EngineConfigState::Unconfigured
+        })
+    }
+
+    // Decode the JSON-encoded IncomingBso's that UniFFI passes to us.
+    //
+    // `incoming` arrives as an already-owned `Vec<String>` - UniFFI copies
+    // each record out of the host language once, at the FFI boundary, so
+    // there's no host-side buffer (eg a `ThinVec<nsCString>` on the C++ side
+    // of a bridged engine) left to borrow from by the time Rust code sees it.
+    // The one copy we can't avoid is `serde_json::from_str` allocating
+    // `IncomingBso`'s own owned fields (eg `payload`); making that borrow from
+    // `inc` instead would mean changing `IncomingBso` itself, a type `sync15`
+    // shares with every other engine, which isn't worth destabilizing here.
+    // What we can cheaply do is bound each envelope's size before parsing it,
+    // so one oversized or corrupt envelope can't balloon peak memory during a
+    // big sync - see `Error::IncomingEnvelopeTooLarge`.
     fn convert_incoming_bsos(&self, incoming: Vec<String>) -> Result<Vec<IncomingBso>> {
         let mut bsos = Vec::with_capacity(incoming.len());
         for inc in incoming {
+            if inc.len() > MAX_INCOMING_ENVELOPE_BYTES {
+                return Err(crate::Error::IncomingEnvelopeTooLarge(inc.len()).into());
+            }
             bsos.push(serde_json::from_str::<IncomingBso>(&inc)?);
         }
         Ok(bsos)
@@ -100,14 +262,27 @@ impl TabsBridgedEngine {
         Ok(bsos)
     }
 
-    pub fn store_incoming(&self, incoming: Vec<String>) -> Result<()> {
+    pub fn store_incoming(&self, incoming: Vec<String>) -> ApiResult<()> {
+        let bsos = self
+            .convert_incoming_bsos(incoming)
+            .map_err(downcast_bridge_error)?;
         self.bridge_impl
-            .store_incoming(self.convert_incoming_bsos(incoming)?)
+            .store_incoming(bsos)
+            .map_err(downcast_bridge_error)
     }
 
-    pub fn apply(&self) -> Result<Vec<String>> {
-        let apply_results = self.bridge_impl.apply()?;
+    pub fn apply(&self) -> ApiResult<Vec<String>> {
+        let apply_results = self.bridge_impl.apply().map_err(downcast_bridge_error)?;
         self.convert_outgoing_bsos(apply_results.records)
+            .map_err(downcast_bridge_error)
+    }
+
+    /// The incoming/outgoing counts from the most recently completed
+    /// `apply()`, as compact JSON, for Desktop's sync ping to fold in - see
+    /// `TabsEngine::last_sync_telemetry_json`. `None` until the first
+    /// `apply()` completes.
+    pub fn last_sync_telemetry_json(&self) -> Option<String> {
+        self.engine.last_sync_telemetry_json()
     }
 
     pub fn set_uploaded(&self, server_modified_millis: i64, guids: Vec<SyncGuid>) -> Result<()> {
@@ -172,6 +347,9 @@ mod tests {
                         fxa_device_id: None,
                         device_name: "my device".to_string(),
                         device_type: sync15::DeviceType::Unknown,
+                        capabilities: vec![],
+                        os: None,
+                        form_factor: None,
                     },
                 ),
                 (
@@ -180,6 +358,9 @@ mod tests {
                         fxa_device_id: None,
                         device_name: "device with no tabs".to_string(),
                         device_type: DeviceType::Unknown,
+                        capabilities: vec![],
+                        os: None,
+                        form_factor: None,
                     },
                 ),
                 (
@@ -188,6 +369,9 @@ mod tests {
                         fxa_device_id: None,
                         device_name: "device with a tab".to_string(),
                         device_type: DeviceType::Unknown,
+                        capabilities: vec![],
+                        os: None,
+                        form_factor: None,
                     },
                 ),
             ]),
@@ -312,4 +496,142 @@ mod tests {
         assert_eq!(bridge.last_sync().unwrap(), 0);
         assert!(bridge.sync_id().unwrap().is_none());
     }
+
+    #[test]
+    fn test_pause_resume() {
+        env_logger::try_init().ok();
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test-pause-resume"));
+        let bridge = store.bridged_engine();
+
+        // Not paused yet, so this should behave like a normal sync_started.
+        bridge.sync_started().expect("should not be paused yet");
+
+        bridge.pause().unwrap();
+        let err = bridge.sync_started().expect_err("should be paused");
+        assert!(matches!(err, crate::TabsApiError::SyncPaused));
+
+        // Local writes keep working while paused.
+        store.set_local_tabs(vec![]);
+
+        bridge.resume().unwrap();
+        bridge.sync_started().expect("should no longer be paused");
+    }
+
+    #[test]
+    fn test_storage_version() {
+        let store = Arc::new(TabsStore::new_with_mem_path("test-storage-version"));
+        let bridge = store.bridged_engine();
+
+        assert_eq!(
+            bridge.storage_version(),
+            crate::schema::TabsMigrationLogic::END_VERSION as i64
+        );
+    }
+
+    #[test]
+    fn test_store_incoming_rejects_oversized_envelope() {
+        env_logger::try_init().ok();
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test-oversized-envelope"));
+        let bridge = store.bridged_engine();
+
+        let huge_envelope = "x".repeat(MAX_INCOMING_ENVELOPE_BYTES + 1);
+        let err = bridge
+            .store_incoming(vec![huge_envelope])
+            .expect_err("should reject an envelope over the size bound");
+        assert!(matches!(
+            err,
+            crate::TabsApiError::UnexpectedTabsError { .. }
+        ));
+    }
+
+    #[test]
+    fn test_store_incoming_large_batch() {
+        env_logger::try_init().ok();
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test-large-batch"));
+        let bridge = store.bridged_engine();
+        bridge
+            .prepare_for_sync(
+                &serde_json::to_string(&ClientData {
+                    local_client_id: "local-device".to_string(),
+                    recent_clients: HashMap::new(),
+                })
+                .unwrap(),
+            )
+            .expect("should prepare");
+
+        // A batch of ordinary-sized envelopes shouldn't trip the per-envelope
+        // bound, however many of them there are.
+        let envelopes: Vec<String> = (0..1000)
+            .map(|i| {
+                serde_json::to_string(&IncomingBso::new_test_tombstone(SyncGuid::from(format!(
+                    "envelope-{i}"
+                ))))
+                .unwrap()
+            })
+            .collect();
+        bridge.store_incoming(envelopes).unwrap();
+    }
+
+    #[test]
+    fn test_store_incoming_before_prepare_for_sync_fails() {
+        env_logger::try_init().ok();
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test-not-configured"));
+        let bridge = store.bridged_engine();
+
+        assert_eq!(
+            bridge.get_debug_state().unwrap(),
+            EngineConfigState::Unconfigured
+        );
+        let err = bridge
+            .store_incoming(vec![])
+            .expect_err("should reject use before prepare_for_sync");
+        assert!(matches!(err, crate::TabsApiError::NotConfigured));
+
+        bridge
+            .prepare_for_sync(
+                &serde_json::to_string(&ClientData {
+                    local_client_id: "local-device".to_string(),
+                    recent_clients: HashMap::new(),
+                })
+                .unwrap(),
+            )
+            .expect("should prepare");
+        assert_eq!(
+            bridge.get_debug_state().unwrap(),
+            EngineConfigState::Configured
+        );
+        bridge.store_incoming(vec![]).expect("should now succeed");
+    }
+
+    #[test]
+    fn test_last_sync_telemetry_json() {
+        env_logger::try_init().ok();
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test-last-sync-telemetry"));
+        let bridge = store.bridged_engine();
+
+        assert_eq!(bridge.last_sync_telemetry_json(), None);
+
+        bridge
+            .prepare_for_sync(
+                &serde_json::to_string(&ClientData {
+                    local_client_id: "local-device".to_string(),
+                    recent_clients: HashMap::new(),
+                })
+                .unwrap(),
+            )
+            .expect("should prepare");
+        bridge.store_incoming(vec![]).expect("should store");
+        bridge.apply().expect("should apply");
+
+        let telemetry = bridge
+            .last_sync_telemetry_json()
+            .expect("apply should have recorded a snapshot");
+        let telemetry: serde_json::Value = serde_json::from_str(&telemetry).unwrap();
+        assert_eq!(telemetry["outgoing_sent"], 0);
+    }
 }
@@ -2,20 +2,55 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::observer::{InvalidateReason, TabsChangeSummary};
 use crate::schema;
-use crate::storage::{ClientRemoteTabs, RemoteTab, TABS_CLIENT_TTL};
+use crate::storage::{ClientRemoteTabs, CloseTabCommand, CommandAck, RemoteTab, TABS_CLIENT_TTL};
 use crate::store::TabsStore;
-use crate::sync::record::{TabsRecord, TabsRecordTab};
+use crate::sync::record::{
+    CommandAck as RecordCommandAck, TabCommand as RecordTabCommand, TabsRecord, TabsRecordTab,
+};
 use anyhow::Result;
+use interrupt_support::{Interruptee, NeverInterrupts};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock, Weak};
-use sync15::bso::{IncomingBso, OutgoingBso, OutgoingEnvelope};
+use sync15::bso::{IncomingBso, IncomingKind, OutgoingBso, OutgoingEnvelope};
 use sync15::engine::{
     CollSyncIds, CollectionRequest, EngineSyncAssociation, SyncEngine, SyncEngineId,
 };
 use sync15::{telemetry, ClientData, CollectionName, DeviceType, RemoteClient, ServerTimestamp};
 use sync_guid::Guid;
 
+// How far back (ms) a new server timestamp can drop from what we've already
+// stored before we treat it as more than transient clock skew and force a full
+// mirror refresh rather than just clamping - see `TabsEngine::set_last_sync`.
+const TIMESTAMP_REGRESSION_REFRESH_THRESHOLD_MS: i64 = 24 * 60 * 60 * 1000; // 1 day
+
+// A generous ceiling on how many incoming tabs we'll stage in a single sync
+// session (ie between one `on_sync_started` and the next) - see
+// `TabsEngine::stage_incoming`. Guards against a sync manager bug that keeps
+// calling `stage_incoming` without ever reaching `apply`, which would
+// otherwise let us churn through an unbounded number of writes.
+const MAX_STAGED_TABS_PER_SESSION: u32 = 25_000;
+
+// These counts and durations feed telemetry, not sync logic - on the
+// vanishingly unlikely chance one overflows the target type (eg more than
+// u32::MAX tabs in a single sync), saturate instead of silently wrapping,
+// which would report a misleadingly small number rather than an obviously
+// capped one. `usize` is also narrower than `u32` on no target we support, but
+// isn't guaranteed wider either (eg 32-bit targets), so this is a real `TryInto`
+// rather than an infallible widening.
+fn saturating_u32(value: usize) -> u32 {
+    u32::try_from(value).unwrap_or(u32::MAX)
+}
+
+#[cfg(feature = "glean-metrics")]
+fn saturating_u64_millis(elapsed: std::time::Duration) -> u64 {
+    u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+}
+
 // Our "sync manager" will use whatever is stashed here.
 lazy_static::lazy_static! {
     // Mutex: just taken long enough to update the inner stuff
@@ -51,6 +86,9 @@ impl ClientRemoteTabs {
             client_name: remote_client.device_name.clone(),
             device_type: remote_client.device_type,
             last_modified: last_modified.as_millis(),
+            capabilities: remote_client.capabilities.clone(),
+            os: remote_client.os.clone(),
+            form_factor: remote_client.form_factor.clone(),
             remote_tabs: record.tabs.iter().map(RemoteTab::from_record_tab).collect(),
         }
     }
@@ -69,10 +107,17 @@ impl ClientRemoteTabs {
             client_name: record.client_name,
             device_type: DeviceType::Unknown,
             last_modified: last_modified.as_millis(),
+            capabilities: Vec::new(),
+            os: None,
+            form_factor: None,
             remote_tabs: record.tabs.iter().map(RemoteTab::from_record_tab).collect(),
         }
     }
-    fn to_record(&self) -> TabsRecord {
+    // `pub(crate)` rather than private so `import::validate_line` can reuse
+    // this to turn an imported `ClientRemoteTabs` back into the `TabsRecord`
+    // `TabsStorage::replace_remote_tabs` expects - the same conversion this
+    // module uses above when building an outgoing BSO from the local record.
+    pub(crate) fn to_record(&self) -> TabsRecord {
         TabsRecord {
             id: self.client_id.clone(),
             client_name: self.client_name.clone(),
@@ -81,6 +126,11 @@ impl ClientRemoteTabs {
                 .iter()
                 .map(RemoteTab::to_record_tab)
                 .collect(),
+            // Acks and outgoing commands aren't part of `ClientRemoteTabs` -
+            // callers that need to emit them (eg `TabsEngine::apply`) set
+            // `record.acks`/`record.commands` directly after calling this.
+            acks: Vec::new(),
+            commands: Vec::new(),
         }
     }
 }
@@ -93,6 +143,9 @@ impl RemoteTab {
             icon: tab.icon.clone(),
             last_used: tab.last_used.checked_mul(1000).unwrap_or_default(),
             inactive: tab.inactive,
+            last_modified: tab
+                .last_modified
+                .map(|lm| lm.checked_mul(1000).unwrap_or_default()),
         }
     }
     pub(super) fn to_record_tab(&self) -> TabsRecordTab {
@@ -102,16 +155,115 @@ impl RemoteTab {
             icon: self.icon.clone(),
             last_used: self.last_used.checked_div(1000).unwrap_or_default(),
             inactive: self.inactive,
+            last_modified: self
+                .last_modified
+                .map(|lm| lm.checked_div(1000).unwrap_or_default()),
+        }
+    }
+}
+
+impl CommandAck {
+    fn to_record_ack(&self) -> RecordCommandAck {
+        RecordCommandAck {
+            command_id: self.command_id.clone(),
+            status: self.status.clone(),
+            timestamp: self.timestamp.checked_div(1000).unwrap_or_default(),
+        }
+    }
+
+    fn from_record_ack(ack: &RecordCommandAck) -> Self {
+        Self {
+            command_id: ack.command_id.clone(),
+            status: ack.status.clone(),
+            timestamp: ack.timestamp.checked_mul(1000).unwrap_or_default(),
+        }
+    }
+}
+
+impl CloseTabCommand {
+    fn to_record_command(&self) -> RecordTabCommand {
+        RecordTabCommand {
+            command_id: self.command_id.clone(),
+            target_client_id: self.target_client_id.clone(),
+            url: self.url.clone(),
+            created_at: self.created_at.checked_div(1000).unwrap_or_default(),
         }
     }
 }
 
+/// Coarse state returned by `TabsEngine::debug_state`/`TabsBridgedEngine::get_debug_state`,
+/// for about:sync-style diagnostics. See `TabsEngine::debug_state` for why
+/// this only has three variants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EngineConfigState {
+    /// `prepare_for_sync` hasn't set a local client id yet - `stage_incoming`
+    /// and `apply` will fail with `TabsApiError::NotConfigured` rather than
+    /// risk silently misbehaving.
+    Unconfigured,
+    /// `prepare_for_sync` has run - the engine is ready for sync methods.
+    Configured,
+    /// The database has been deleted and recreated due to corruption too many
+    /// times in a row - see `TabsEngine::require_not_degraded`. Sync methods
+    /// will fail with `TabsApiError::DatabaseDegraded` until the user or
+    /// support intervenes.
+    Degraded,
+}
+
+/// Passed to `TabsEngine::abort_sync`, deciding what happens to whatever's
+/// been staged so far this session (`TabsEngine::staged_tabs_this_session`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AbortSyncReason {
+    /// There's no sync left to resume (eg the user signed out, or the account
+    /// was disconnected) - drop the session's staging progress entirely.
+    Discard,
+    /// The interruption is expected to be transient (eg the browser went
+    /// offline) - keep the session's staging progress so a follow-up sync
+    /// still gets capped correctly against `MAX_STAGED_TABS_PER_SESSION`
+    /// rather than double-counting what was already staged.
+    Resume,
+}
+
 // This is the implementation of syncing, which is used by the 2 different "sync engines"
 // (We hope to get these 2 engines even closer in the future, but for now, we suck this up)
 pub struct TabsEngine {
     pub(super) store: Arc<TabsStore>,
     // local_id is made public for use in examples/tabs-sync
     pub local_id: RwLock<String>,
+    // Lets an embedder (or a shutdown/cancel signal) interrupt us at the
+    // yield points sprinkled through `stage_incoming`, so a large first-sync
+    // apply doesn't block the caller's thread for the whole batch.
+    //
+    // This is an `Arc` rather than a `Box` so callers can clone it out from
+    // under the lock before invoking it (see `stage_incoming`) - an embedder's
+    // `Interruptee` impl is foreign code that may call straight back into us
+    // (eg to install a new interruptee), and doing that while we're still
+    // holding our own lock would deadlock.
+    interruptee: RwLock<Arc<dyn Interruptee + Send + Sync>>,
+    // Set by `stage_incoming`, read by `apply` to build the `TabsChangeSummary`
+    // passed to any registered `TabsSyncObserver`.
+    incoming_tabs_this_sync: Cell<u32>,
+    // IDs of remote clients whose tabs this sync staged or tombstoned - set by
+    // `stage_incoming`, read by `apply` for the same `TabsChangeSummary` above.
+    // A `RefCell` rather than a `Cell` since, unlike the counters alongside it,
+    // `Vec<String>` isn't `Copy`.
+    changed_client_ids_this_sync: RefCell<Vec<String>>,
+    // Running total of incoming tabs staged since the last `on_sync_started`,
+    // checked against `MAX_STAGED_TABS_PER_SESSION` by `stage_incoming`.
+    staged_tabs_this_session: Cell<u32>,
+    // Flipped by `abort_sync` and checked by `require_not_aborted` - unlike
+    // `interruptee` (an embedder-installed, possibly-`NeverInterrupts` hook
+    // used for cooperative between-record cancellation), this is a signal
+    // this crate controls itself, so `abort_sync` always has somewhere to
+    // record that it was called regardless of what the embedder wired up. An
+    // `AtomicBool` rather than a `Cell` since, unlike everything else on this
+    // struct, it's meant to be flipped from a different thread than the one
+    // running the sync. Cleared by `on_sync_started` so a later sync attempt
+    // isn't stuck bailing out forever.
+    aborted: AtomicBool,
+    // Set at the end of `apply`, read by `last_sync_telemetry_json` - see its
+    // doc comment for why this exists alongside the `telem` parameter `apply`
+    // already accumulates into.
+    last_sync_telemetry: RwLock<Option<String>>,
 }
 
 impl TabsEngine {
@@ -119,14 +271,65 @@ impl TabsEngine {
         Self {
             store,
             local_id: Default::default(),
+            interruptee: RwLock::new(Arc::new(NeverInterrupts)),
+            incoming_tabs_this_sync: Cell::new(0),
+            changed_client_ids_this_sync: RefCell::new(Vec::new()),
+            staged_tabs_this_session: Cell::new(0),
+            aborted: AtomicBool::new(false),
+            last_sync_telemetry: RwLock::new(None),
         }
     }
 
+    /// The incoming/outgoing counts from the most recently completed
+    /// `apply()`, as compact JSON. The sync-manager-registered path gets this
+    /// for free from the `telem: &mut telemetry::Engine` it threads through
+    /// every engine's `stage_incoming`/`apply` and folds into its own sync
+    /// ping; the bridged (Desktop) path has no equivalent ping to fold into,
+    /// so `TabsBridgedEngine::last_sync_telemetry_json` exposes this instead.
+    /// `None` until the first `apply()` completes.
+    pub fn last_sync_telemetry_json(&self) -> Option<String> {
+        self.last_sync_telemetry.read().unwrap().clone()
+    }
+
+    /// Installs the `Interruptee` used to check for cooperative cancellation
+    /// between records while applying incoming tabs. Defaults to `NeverInterrupts`.
+    pub fn set_interruptee(&self, interruptee: Arc<dyn Interruptee + Send + Sync>) {
+        *self.interruptee.write().unwrap() = interruptee;
+    }
+
     pub fn set_last_sync(&self, last_sync: ServerTimestamp) -> Result<()> {
         let mut storage = self.store.storage.lock().unwrap();
-        log::debug!("Updating last sync to {}", last_sync);
         let last_sync_millis = last_sync.as_millis();
-        Ok(storage.put_meta(schema::LAST_SYNC_META_KEY, &last_sync_millis)?)
+        let current = storage.get_meta::<i64>(schema::LAST_SYNC_META_KEY)?;
+        // A server timestamp older than what we've already stored would move
+        // `since` backwards, risking duplicate (or, if we instead trusted it and
+        // something else relied on monotonicity, missed) downloads next sync - so
+        // never let it regress. A large-enough jump back is also a sign the
+        // collection may have been reset or restored server-side, in which case
+        // our mirror could be stale in ways clamping alone won't fix - so force a
+        // full refresh next time rather than trusting incremental sync.
+        let to_store = match current {
+            Some(current) if last_sync_millis < current => {
+                // A malformed server timestamp near `i64::MIN`/`MAX` could
+                // otherwise overflow this subtraction - saturate instead.
+                let regressed_by = current.saturating_sub(last_sync_millis);
+                log::warn!(
+                    "server timestamp regressed by {}ms ({} -> {}) - clamping to {}",
+                    regressed_by,
+                    current,
+                    last_sync_millis,
+                    current
+                );
+                if regressed_by > TIMESTAMP_REGRESSION_REFRESH_THRESHOLD_MS {
+                    log::warn!("regression is large enough to force a full mirror refresh");
+                    storage.put_meta(schema::FORCE_MIRROR_REFRESH_KEY, &true)?;
+                }
+                current
+            }
+            _ => last_sync_millis,
+        };
+        log::debug!("Updating last sync to {}", to_store);
+        storage.put_meta(schema::LAST_SYNC_META_KEY, &to_store)
     }
 
     pub fn get_last_sync(&self) -> Result<Option<ServerTimestamp>> {
@@ -134,6 +337,163 @@ impl TabsEngine {
         let millis = storage.get_meta::<i64>(schema::LAST_SYNC_META_KEY)?;
         Ok(millis.map(ServerTimestamp))
     }
+
+    // Raised by `stage_incoming`/`apply` when `prepare_for_sync` hasn't run
+    // yet - without this, an unconfigured engine would silently misbehave
+    // instead of failing loudly: `local_id` defaults to an empty string, and
+    // an incoming envelope with an empty id (unlikely, but not impossible for
+    // a confused or hostile server) would be mistaken for our own record and
+    // dropped in `stage_incoming`.
+    pub(crate) fn require_configured(&self) -> Result<()> {
+        if self.local_id.read().unwrap().is_empty() {
+            return Err(crate::Error::NotConfigured.into());
+        }
+        Ok(())
+    }
+
+    /// Coarse lifecycle state for diagnostics - see
+    /// `TabsBridgedEngine::get_debug_state`. Unlike an XPCOM engine, this
+    /// crate has no separate "Open"/"TornDown" phases to report: the sqlite
+    /// connection underneath is opened and closed per-operation (see
+    /// `TabsStorage::open_or_create`) rather than held for the engine's
+    /// lifetime, so there's nothing else meaningful to distinguish here.
+    pub fn debug_state(&self) -> EngineConfigState {
+        if self.is_degraded() {
+            EngineConfigState::Degraded
+        } else if self.local_id.read().unwrap().is_empty() {
+            EngineConfigState::Unconfigured
+        } else {
+            EngineConfigState::Configured
+        }
+    }
+
+    /// Whether repeated storage corruption has put us into a degraded state -
+    /// see `TabsStorage::is_degraded`.
+    pub(crate) fn is_degraded(&self) -> bool {
+        self.store.storage.lock().unwrap().is_degraded()
+    }
+
+    // Raised by `on_sync_started` once `is_degraded` reports the database has
+    // been corrupted and recreated too many times - there's no amount of
+    // retrying that fixes a persistently corrupt disk, so we stop trying
+    // rather than keep silently wiping and resyncing the mirror. Notifies any
+    // registered `TabsSyncObserver` every time this is hit, not just the
+    // first, since the condition persists until a human intervenes.
+    fn require_not_degraded(&self) -> Result<()> {
+        if !self.is_degraded() {
+            return Ok(());
+        }
+        let sync_observer = self.store.sync_observer.read().unwrap().clone();
+        if let Some(sync_observer) = sync_observer {
+            sync_observer.on_degraded();
+        }
+        Err(crate::Error::DatabaseDegraded.into())
+    }
+
+    /// See `TabsBridgedEngine::pause`/`resume` - troubleshooting sometimes
+    /// needs tabs sync frozen without touching global sync. Never affects
+    /// local write APIs like `set_local_tabs`.
+    pub fn pause(&self) -> Result<()> {
+        self.store.storage.lock().unwrap().set_sync_paused(true)
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        self.store.storage.lock().unwrap().set_sync_paused(false)
+    }
+
+    /// Whether `pause()` has frozen syncing - see
+    /// `TabsBridgedEngineAdaptor::sync_started`, which checks this so callers
+    /// outside this module don't need to reach through `store.storage`
+    /// themselves.
+    pub(crate) fn is_sync_paused(&self) -> Result<bool> {
+        self.store.storage.lock().unwrap().is_sync_paused()
+    }
+
+    /// Immediately aborts an in-flight sync - eg the browser going offline
+    /// mid-sync - without waiting for the current `stage_incoming`/`apply`
+    /// call to return on its own. Safe to call from a different thread than
+    /// the one running the sync: it only ever flips `aborted`, which
+    /// `require_not_aborted` checks at the top of `stage_incoming`/`apply`
+    /// and once per record in `stage_incoming`'s loop, so the in-flight call
+    /// bails out with `Error::SyncAborted` at its next check rather than
+    /// finishing the batch. There's no separate "sync lock" to release here -
+    /// the only lock either of those methods hold (`store.storage`) is
+    /// dropped automatically the moment the interrupted call returns.
+    pub fn abort_sync(&self, reason: AbortSyncReason) {
+        self.aborted.store(true, Ordering::SeqCst);
+        if reason == AbortSyncReason::Discard {
+            self.staged_tabs_this_session.set(0);
+            self.incoming_tabs_this_sync.set(0);
+            self.changed_client_ids_this_sync.borrow_mut().clear();
+        }
+    }
+
+    /// See `abort_sync`. Unlike `require_not_degraded`, this isn't reported
+    /// to `TabsSyncObserver` - an abort is something the caller asked for, not
+    /// a surprise worth flagging.
+    fn require_not_aborted(&self) -> Result<()> {
+        if self.aborted.load(Ordering::SeqCst) {
+            return Err(crate::Error::SyncAborted.into());
+        }
+        Ok(())
+    }
+
+    // Called once at the start of a sync session, before any `stage_incoming` -
+    // via `SyncEngine::prepare_for_sync` for the sync-manager-registered path,
+    // and via `TabsBridgedEngineAdaptor::sync_started` for the bridged (Desktop)
+    // path. Resets the per-session staging cap and purges clients we haven't
+    // seen in a while, so a sync that keeps calling `stage_incoming` without
+    // ever reaching `apply` can't grow our storage without bound.
+    pub(crate) fn on_sync_started(&self) -> Result<()> {
+        self.require_not_degraded()?;
+        self.aborted.store(false, Ordering::SeqCst);
+        self.staged_tabs_this_session.set(0);
+        self.store.storage.lock().unwrap().remove_stale_clients()
+    }
+}
+
+// Renames `from` to `to` in `map`, but only if `to` isn't already present -
+// a self-hosted server running a mixed fleet could plausibly send either
+// dialect, and the modern field always wins if somehow both are present.
+fn rename_legacy_field(map: &mut serde_json::Map<String, serde_json::Value>, from: &str, to: &str) {
+    if !map.contains_key(to) {
+        if let Some(value) = map.remove(from) {
+            map.insert(to.to_string(), value);
+        }
+    }
+}
+
+// Tolerant fixup for `TabsEngine::stage_incoming`, gated behind the
+// "legacy-envelope-compat" engine pref (see
+// `TabsStorage::legacy_envelope_compat_enabled`) - some self-hosted Sync
+// servers are still fronting clients old enough to write the pre-camelCase
+// snake_case field names this crate's `TabsRecord`/`TabsRecordTab`/
+// `CommandAck` used before they adopted `#[serde(rename_all = "camelCase")]`.
+// Renames those fields in place before `TabsRecord` ever tries to deserialize
+// the payload - outgoing records are never affected by this, since
+// `TabsRecord`'s `Serialize` impl always writes the modern camelCase form
+// regardless of this pref (see `TabsEngine::apply`).
+pub(crate) fn fixup_legacy_envelope(json: &mut serde_json::Value) {
+    let Some(record) = json.as_object_mut() else {
+        return;
+    };
+    rename_legacy_field(record, "client_name", "clientName");
+    if let Some(tabs) = record.get_mut("tabs").and_then(|v| v.as_array_mut()) {
+        for tab in tabs {
+            if let Some(tab) = tab.as_object_mut() {
+                rename_legacy_field(tab, "url_history", "urlHistory");
+                rename_legacy_field(tab, "last_used", "lastUsed");
+                rename_legacy_field(tab, "last_modified", "lastModified");
+            }
+        }
+    }
+    if let Some(acks) = record.get_mut("acks").and_then(|v| v.as_array_mut()) {
+        for ack in acks {
+            if let Some(ack) = ack.as_object_mut() {
+                rename_legacy_field(ack, "command_id", "commandId");
+            }
+        }
+    }
 }
 
 impl SyncEngine for TabsEngine {
@@ -142,6 +502,10 @@ impl SyncEngine for TabsEngine {
     }
 
     fn prepare_for_sync(&self, get_client_data: &dyn Fn() -> ClientData) -> Result<()> {
+        // Only called for the sync-manager-registered path (see the trait docs),
+        // so it's also our `on_sync_started` hook there - the bridged path gets
+        // its own call via `TabsBridgedEngineAdaptor::sync_started`.
+        self.on_sync_started()?;
         let mut storage = self.store.storage.lock().unwrap();
         // We only know the client list at sync time, but need to return tabs potentially
         // at any time -- so we store the clients in the meta table to be able to properly
@@ -160,56 +524,224 @@ impl SyncEngine for TabsEngine {
         inbound: Vec<IncomingBso>,
         telem: &mut telemetry::Engine,
     ) -> Result<()> {
-        // We don't really "stage" records, we just apply them.
+        self.require_configured()?;
+        self.require_not_aborted()?;
+        // We don't really "stage" records, we just apply them: each batch below
+        // is written straight into `TabsStorage`'s SQLite tables before this
+        // call returns, rather than buffered in memory (eg a `RefCell<Vec<_>>`)
+        // until `apply()` runs. A crash between this call and `apply()` loses
+        // nothing beyond whatever committed writes SQLite itself hadn't
+        // flushed - there's no separate in-memory batch to go missing.
         let local_id = &*self.local_id.read().unwrap();
+
+        // A sync that keeps calling `stage_incoming` without ever reaching
+        // `apply` (eg a sync manager bug retrying a batch forever) shouldn't be
+        // able to make us do an unbounded amount of work - cap how many more
+        // tabs we'll stage this session and drop the rest, loudly.
+        let already_staged = self.staged_tabs_this_session.get();
+        let budget = MAX_STAGED_TABS_PER_SESSION.saturating_sub(already_staged) as usize;
+        let num_capped = inbound.len().saturating_sub(budget);
+        let inbound = if num_capped > 0 {
+            log::warn!(
+                "stage_incoming: session cap of {} reached, dropping {} incoming tabs",
+                MAX_STAGED_TABS_PER_SESSION,
+                num_capped
+            );
+            inbound.into_iter().take(budget).collect::<Vec<_>>()
+        } else {
+            inbound
+        };
+
         let mut remote_tabs = Vec::with_capacity(inbound.len());
+        let mut incoming_acks = Vec::new();
+        let mut incoming_commands_for_us = Vec::new();
+        let mut tombstoned_clients = Vec::new();
+        let mut changed_client_ids = Vec::with_capacity(inbound.len());
 
         let mut incoming_telemetry = telemetry::EngineIncoming::new();
+        // Clone the `Arc` and drop the lock guard before we start calling into it -
+        // `err_if_interrupted` runs foreign code that may re-enter this engine (eg
+        // to install a new interruptee), which would deadlock if we were still
+        // holding our own lock.
+        let interruptee = self.interruptee.read().unwrap().clone();
+        // Read once up front rather than per-record - this is a deliberate,
+        // sync-wide choice, not something that should flap mid-batch.
+        let legacy_envelope_compat = self
+            .store
+            .storage
+            .lock()
+            .unwrap()
+            .legacy_envelope_compat_enabled();
+        let mut length_violations: u32 = 0;
         for incoming in inbound {
+            self.require_not_aborted()?;
+            interruptee.err_if_interrupted()?;
             if incoming.envelope.id == *local_id {
                 // That's our own record, ignore it.
                 continue;
             }
+            // Each envelope's own server-modified time, stored per-client below
+            // via `replace_remote_tabs` so `ClientRemoteTabs::last_modified`
+            // reflects when that client actually last synced - distinct from
+            // (and unrelated to) the bridged-path `apply(ServerTimestamp(0),
+            // ..)` quirk, which is about this *engine's* last-sync bookkeeping,
+            // not per-record staleness.
             let modified = incoming.envelope.modified;
-            let record = match incoming.into_content::<TabsRecord>().content() {
-                Some(record) => record,
-                None => {
-                    // Invalid record or a "tombstone" which tabs don't have.
+            let envelope_id = incoming.envelope.id.clone();
+            let content = if legacy_envelope_compat {
+                incoming.into_content_with_fixup::<TabsRecord>(fixup_legacy_envelope)
+            } else {
+                incoming.into_content::<TabsRecord>()
+            };
+            let mut record = match content.kind {
+                IncomingKind::Content(record) => record,
+                IncomingKind::Tombstone => {
+                    // The client behind this envelope disconnected and told
+                    // the server to delete its record - drop our cached copy
+                    // of it now rather than leaving it to reappear in the
+                    // Synced Tabs list until `remove_stale_clients`'s TTL
+                    // eventually catches up with it.
+                    tombstoned_clients.push(envelope_id.to_string());
+                    incoming_telemetry.applied(1);
+                    continue;
+                }
+                IncomingKind::Malformed => {
                     log::warn!("Ignoring incoming invalid tab");
                     incoming_telemetry.failed(1);
                     continue;
                 }
             };
+            // A remote client could (accidentally or otherwise) send us a
+            // megabyte-long title or URL - cap it the same way we cap our own.
+            for tab in record.tabs.iter_mut() {
+                if crate::storage::sanitize_incoming_tab(tab) {
+                    length_violations += 1;
+                }
+            }
+            // Acks in this record are targeted at whichever client sent the
+            // commands, not necessarily us - but ingesting (and deduping)
+            // them here is harmless even when they're not ours, since
+            // `record_incoming_ack` is keyed by `command_id` alone.
+            incoming_acks.extend(std::mem::take(&mut record.acks));
+            // Unlike acks, commands *are* addressed - only forward the ones
+            // actually targeting us; a command some other client sent to a
+            // third client is none of our business. `envelope_id`, not
+            // `record.id`, is the authoritative sender - same as everywhere
+            // else in this loop.
+            incoming_commands_for_us.extend(
+                std::mem::take(&mut record.commands)
+                    .into_iter()
+                    .filter(|command| command.target_client_id == *local_id)
+                    .map(|command| (envelope_id.to_string(), command)),
+            );
+            // `applied`, not `reconciled`: a remote client's tabs record fully
+            // replaces our mirror of that client (see `replace_remote_tabs`)
+            // rather than being merged field-by-field with a conflicting local
+            // change - our own record is skipped above instead of being
+            // overwritten - so tabs has no two-sided merge for `reconciled` to
+            // count. `ApplyResults::num_reconciled` is correctly always 0 here.
             incoming_telemetry.applied(1);
+            changed_client_ids.push(record.id.clone());
             remote_tabs.push((record, modified));
         }
         telem.incoming(incoming_telemetry);
+        self.incoming_tabs_this_sync
+            .set(saturating_u32(remote_tabs.len()));
+        self.staged_tabs_this_session
+            .set(already_staged.saturating_add(saturating_u32(remote_tabs.len())));
+        changed_client_ids.extend(tombstoned_clients.iter().cloned());
+        *self.changed_client_ids_this_sync.borrow_mut() = changed_client_ids;
         let mut storage = self.store.storage.lock().unwrap();
         // In desktop we might end up here with zero records when doing a quick-write, in
         // which case we don't want to wipe the DB.
-        if !remote_tabs.is_empty() {
-            storage.replace_remote_tabs(remote_tabs)?;
+        if !remote_tabs.is_empty()
+            && !storage.replace_remote_tabs_chunked(remote_tabs, &*interruptee, None)?
+        {
+            // Interrupted partway through a large batch - the chunks that did
+            // commit are each a complete, consistent `INSERT OR REPLACE` (see
+            // `replace_remote_tabs_chunked`), so there's nothing to roll back.
+            // Bail out before the bookkeeping below runs so this sync isn't
+            // recorded as having fully applied what we were given.
+            return Err(interrupt_support::Interrupted.into());
+        }
+        for client_id in &tombstoned_clients {
+            storage.delete_remote_client(client_id)?;
+        }
+        for ack in &incoming_acks {
+            storage.record_incoming_ack(&ack.command_id, &ack.status)?;
         }
         storage.remove_stale_clients()?;
+        storage.compact_snapshot_history()?;
+        storage.reencode_legacy_records()?;
+        storage.record_length_violations(length_violations)?;
+        storage.record_stage_cap_violations(saturating_u32(num_capped))?;
+        // Refresh the synchronous fast-path cache with what we just wrote -
+        // see `TabsStore::get_cached_remote_tabs_json`. Best-effort: a
+        // failure here shouldn't fail the sync that already committed fine.
+        if let Some(crts) = storage.get_for_display(true) {
+            self.store
+                .set_cached_remote_tabs_json(serde_json::to_string(&crts).ok());
+        }
+        drop(storage);
+
+        // Incoming close-tab commands aren't persisted (see
+        // `CloseTabCommand`'s doc comment) - forward each straight to the
+        // observer now, while we still know who sent it. Clone-then-drop the
+        // lock, same reentrancy reason as everywhere else we call into it.
+        if !incoming_commands_for_us.is_empty() {
+            let sync_observer = self.store.sync_observer.read().unwrap().clone();
+            if let Some(sync_observer) = sync_observer {
+                for (sender_client_id, command) in incoming_commands_for_us {
+                    sync_observer.on_close_tab_requested(crate::observer::CloseTabRequest {
+                        command_id: command.command_id,
+                        sender_client_id,
+                        url: command.url,
+                    });
+                }
+            }
+        }
         Ok(())
     }
 
     fn apply(
         &self,
         timestamp: ServerTimestamp,
-        _telem: &mut telemetry::Engine,
+        telem: &mut telemetry::Engine,
     ) -> Result<Vec<OutgoingBso>> {
+        self.require_configured()?;
+        self.require_not_aborted()?;
+        #[cfg(feature = "glean-metrics")]
+        let apply_start = std::time::Instant::now();
         // We've already applied them - really we just need to fetch outgoing.
-        let (local_tabs, remote_clients) = {
+        let (
+            local_tabs,
+            remote_clients,
+            pending_acks,
+            pending_close_commands,
+            outgoing_tabs_trimmed,
+        ) = {
             let mut storage = self.store.storage.lock().unwrap();
+            storage.advance_apply_generation()?;
+            let trimmed_before = storage.get_outgoing_tabs_trimmed()?;
             let local_tabs = storage.prepare_local_tabs_for_upload();
+            let outgoing_tabs_trimmed = saturating_u32(
+                (storage.get_outgoing_tabs_trimmed()? - trimmed_before).max(0) as usize,
+            );
+            let pending_acks = storage.get_pending_command_acks()?;
+            let pending_close_commands = storage.get_pending_close_commands()?;
             let remote_clients: HashMap<String, RemoteClient> = {
                 match storage.get_meta::<String>(schema::REMOTE_CLIENTS_KEY)? {
                     None => HashMap::default(),
                     Some(json) => serde_json::from_str(&json).unwrap(),
                 }
             };
-            (local_tabs, remote_clients)
+            (
+                local_tabs,
+                remote_clients,
+                pending_acks,
+                pending_close_commands,
+                outgoing_tabs_trimmed,
+            )
         };
 
         let local_id = &*self.local_id.read().unwrap();
@@ -217,8 +749,29 @@ impl SyncEngine for TabsEngine {
         if timestamp.0 != 0 {
             self.set_last_sync(timestamp)?;
         }
-        // XXX - outgoing telem?
-        let outgoing = if let Some(local_tabs) = local_tabs {
+
+        // Clone the check out and drop the lock before calling into it - same
+        // reentrancy reason as the observer clones below.
+        let upload_policy_check = self.store.upload_policy_check.read().unwrap().clone();
+        let upload_allowed = match upload_policy_check {
+            Some(check) => check.should_upload(),
+            None => true,
+        };
+        if !upload_allowed {
+            log::debug!(
+                "upload policy denied outgoing tabs - local payload stays pending for next sync"
+            );
+        }
+
+        // We still need to upload if our tabs haven't changed but we owe
+        // someone an ack, or have a close-tab request to issue - otherwise
+        // it'd never go out until our tabs happen to change too.
+        let outgoing = if !upload_allowed {
+            vec![]
+        } else if local_tabs.is_some()
+            || !pending_acks.is_empty()
+            || !pending_close_commands.is_empty()
+        {
             let (client_name, device_type) = remote_clients
                 .get(local_id)
                 .map(|client| (client.device_name.clone(), client.device_type))
@@ -227,27 +780,122 @@ impl SyncEngine for TabsEngine {
                 client_id: local_id.clone(),
                 client_name,
                 device_type,
-                last_modified: 0, // ignored for outgoing records.
-                remote_tabs: local_tabs.to_vec(),
+                last_modified: 0,         // ignored for outgoing records.
+                capabilities: Vec::new(), // ignored for outgoing records.
+                os: None,                 // ignored for outgoing records.
+                form_factor: None,        // ignored for outgoing records.
+                remote_tabs: local_tabs.unwrap_or_default(),
             };
-            log::trace!("outgoing {:?}", local_record);
+            let reveal = self
+                .store
+                .storage
+                .lock()
+                .unwrap()
+                .sensitive_logging_enabled();
+            log::trace!(
+                "outgoing {}",
+                crate::log_redact::redact_client_remote_tabs(&local_record, reveal)
+            );
             let envelope = OutgoingEnvelope {
                 id: local_id.as_str().into(),
                 ttl: Some(TABS_CLIENT_TTL),
                 ..Default::default()
             };
-            vec![OutgoingBso::from_content(
-                envelope,
-                local_record.to_record(),
-            )?]
+            let mut record = local_record.to_record();
+            record.acks = pending_acks.iter().map(CommandAck::to_record_ack).collect();
+            record.commands = pending_close_commands
+                .iter()
+                .map(CloseTabCommand::to_record_command)
+                .collect();
+            vec![OutgoingBso::from_content(envelope, record)?]
         } else {
             vec![]
         };
+
+        let mut outgoing_telemetry = telemetry::EngineOutgoing::new();
+        outgoing_telemetry.sent(outgoing.len());
+        telem.outgoing(outgoing_telemetry);
+
+        // Snapshot the counts `telem` has accumulated so far as compact JSON -
+        // see `last_sync_telemetry_json`'s doc comment. `telem` itself can't
+        // be serialized directly here: it's borrowed from the caller, who
+        // hasn't finished accumulating into it yet (eg `sync_finished` hasn't
+        // run), and `telemetry::Engine`'s `Serialize` impl requires that.
+        #[derive(Serialize)]
+        struct ApplyTelemetrySnapshot<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            incoming: Option<&'a telemetry::EngineIncoming>,
+            outgoing_sent: usize,
+            // Our own tabs dropped this sync for exceeding `MAX_PAYLOAD_SIZE` -
+            // see `TabsStorage::prepare_local_tabs_for_upload`. `sync15::
+            // telemetry::EngineOutgoing` has no field for this, so (like the
+            // rest of this snapshot) it's only available tabs-locally.
+            #[serde(skip_serializing_if = "is_zero")]
+            outgoing_tabs_trimmed: u32,
+        }
+        fn is_zero(value: &u32) -> bool {
+            *value == 0
+        }
+        *self.last_sync_telemetry.write().unwrap() =
+            serde_json::to_string(&ApplyTelemetrySnapshot {
+                incoming: telem.get_incoming().as_ref(),
+                outgoing_sent: outgoing.len(),
+                outgoing_tabs_trimmed,
+            })
+            .ok();
+
+        // Clone the observer out and drop the lock before calling into it - it's
+        // foreign (embedder-provided) code that may call straight back into the
+        // store (eg to re-install an observer), which would deadlock if we still
+        // held our own lock.
+        #[cfg(feature = "glean-metrics")]
+        let observer = self.store.glean_observer.read().unwrap().clone();
+        #[cfg(feature = "glean-metrics")]
+        if let Some(observer) = observer {
+            observer.apply_duration(saturating_u64_millis(apply_start.elapsed()));
+            observer.incoming_outgoing_counts(0, saturating_u32(outgoing.len()));
+        }
+
+        // Same clone-then-drop-the-lock dance as above, for the same reason.
+        let sync_observer = self.store.sync_observer.read().unwrap().clone();
+        if let Some(sync_observer) = sync_observer {
+            sync_observer.on_apply(TabsChangeSummary {
+                incoming_tabs: self.incoming_tabs_this_sync.get(),
+                outgoing_tabs: saturating_u32(outgoing.len()),
+                changed_client_ids: self.changed_client_ids_this_sync.borrow().clone(),
+            });
+        }
+
         Ok(outgoing)
     }
 
     fn set_uploaded(&self, new_timestamp: ServerTimestamp, ids: Vec<Guid>) -> Result<()> {
         log::info!("sync uploaded {} records", ids.len());
+        #[cfg(feature = "glean-metrics")]
+        {
+            let latency_ms = self
+                .store
+                .storage
+                .lock()
+                .unwrap()
+                .take_local_tabs_latency_ms();
+            if let Some(latency_ms) = latency_ms {
+                // Clone the observer out and drop the lock before calling into it,
+                // for the same reentrancy reason as everywhere else we do this.
+                let observer = self.store.glean_observer.read().unwrap().clone();
+                if let Some(observer) = observer {
+                    observer.upload_latency_ms(latency_ms);
+                }
+            }
+        }
+        // Our record (and any acks/commands it carried) is now confirmed on
+        // the server - don't upload those again next sync, and don't
+        // re-upload our tabs either until they actually change.
+        let mut storage = self.store.storage.lock().unwrap();
+        storage.clear_pending_command_acks()?;
+        storage.clear_pending_close_commands()?;
+        storage.mark_local_tabs_uploaded();
+        drop(storage);
         self.set_last_sync(new_timestamp)?;
         Ok(())
     }
@@ -256,7 +904,18 @@ impl SyncEngine for TabsEngine {
         &self,
         server_timestamp: ServerTimestamp,
     ) -> Result<Option<CollectionRequest>> {
-        let since = self.get_last_sync()?.unwrap_or_default();
+        let mut storage = self.store.storage.lock().unwrap();
+        let force_refresh = storage
+            .get_meta::<bool>(schema::FORCE_MIRROR_REFRESH_KEY)?
+            .unwrap_or(false);
+        let since = if force_refresh {
+            log::info!("forcing a full mirror refresh after an earlier timestamp regression");
+            storage.delete_meta(schema::FORCE_MIRROR_REFRESH_KEY)?;
+            ServerTimestamp(0)
+        } else {
+            drop(storage);
+            self.get_last_sync()?.unwrap_or_default()
+        };
         Ok(if since == server_timestamp {
             None
         } else {
@@ -272,6 +931,8 @@ impl SyncEngine for TabsEngine {
         self.set_last_sync(ServerTimestamp(0))?;
         let mut storage = self.store.storage.lock().unwrap();
         storage.delete_meta(schema::REMOTE_CLIENTS_KEY)?;
+        // Also drops our own in-memory `filter_index` - the built-in cache rides
+        // along on this same call rather than needing its own subscription.
         storage.wipe_remote_tabs()?;
         match assoc {
             EngineSyncAssociation::Disconnected => {
@@ -283,14 +944,34 @@ impl SyncEngine for TabsEngine {
                 storage.put_meta(schema::COLLECTION_SYNCID_META_KEY, &ids.coll.to_string())?;
             }
         };
+        drop(storage);
+        // Clone the observer out and drop the lock before calling into it, for the
+        // same reentrancy reason as everywhere else we do this.
+        let sync_observer = self.store.sync_observer.read().unwrap().clone();
+        if let Some(sync_observer) = sync_observer {
+            sync_observer.on_invalidate(InvalidateReason::Reset);
+            sync_observer.on_reset();
+        }
         Ok(())
     }
 
     fn wipe(&self) -> Result<()> {
         self.reset(&EngineSyncAssociation::Disconnected)?;
+        let mut storage = self.store.storage.lock().unwrap();
+        // An account disconnect means every other device's tabs are stale
+        // too, not just our own sync bookkeeping above - drop the remote
+        // mirror we'd otherwise keep showing in the Synced Tabs panel.
+        storage.wipe_remote_tabs()?;
         // not clear why we need to wipe the local tabs - the app is just going
         // to re-add them?
-        self.store.storage.lock().unwrap().wipe_local_tabs();
+        storage.wipe_local_tabs();
+        drop(storage);
+        self.store.set_cached_remote_tabs_json(None);
+        let sync_observer = self.store.sync_observer.read().unwrap().clone();
+        if let Some(sync_observer) = sync_observer {
+            sync_observer.on_invalidate(InvalidateReason::Wipe);
+            sync_observer.on_wipe();
+        }
         Ok(())
     }
 
@@ -324,14 +1005,28 @@ impl crate::TabsStore {
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::observer::TabsSyncObserver;
     use serde_json::json;
     use sync15::bso::IncomingBso;
 
+    // Most tests don't care who the local client is - just that `engine` is
+    // configured enough for `stage_incoming`/`apply` to run at all. See
+    // `TabsEngine::require_configured`.
+    fn configure(engine: &TabsEngine) {
+        engine
+            .prepare_for_sync(&|| ClientData {
+                local_client_id: "local-device".to_string(),
+                recent_clients: HashMap::new(),
+            })
+            .expect("should prepare");
+    }
+
     #[test]
     fn test_incoming_tabs() {
         env_logger::try_init().ok();
 
         let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path("test-incoming")));
+        configure(&engine);
 
         let records = vec![
             json!({
@@ -395,7 +1090,7 @@ pub mod test {
 
         // now check the store has what we think it has.
         let mut storage = engine.store.storage.lock().unwrap();
-        let mut crts = storage.get_remote_tabs().expect("should work");
+        let mut crts = storage.get_remote_tabs(true).expect("should work");
         crts.sort_by(|a, b| a.client_name.partial_cmp(&b.client_name).unwrap());
         assert_eq!(crts.len(), 2, "we currently include devices with no tabs");
         let crt = &crts[0];
@@ -410,6 +1105,103 @@ pub mod test {
         assert_eq!(crt.remote_tabs.len(), 0);
     }
 
+    fn legacy_dialect_record() -> serde_json::Value {
+        json!({
+            "id": "device-legacy",
+            "client_name": "legacy device",
+            "tabs": [{
+                "title": "the title",
+                "url_history": ["https://mozilla.org/"],
+                "icon": "https://mozilla.org/icon",
+                "last_used": 1643764207,
+            }],
+        })
+    }
+
+    #[test]
+    fn test_fixup_legacy_envelope_renames_snake_case_fields() {
+        let mut value = legacy_dialect_record();
+        fixup_legacy_envelope(&mut value);
+        assert_eq!(value["clientName"], "legacy device");
+        assert!(value.get("client_name").is_none());
+        let tab = &value["tabs"][0];
+        assert_eq!(tab["urlHistory"][0], "https://mozilla.org/");
+        assert_eq!(tab["lastUsed"], 1643764207);
+        assert!(tab.get("url_history").is_none());
+        assert!(tab.get("last_used").is_none());
+    }
+
+    #[test]
+    fn test_fixup_legacy_envelope_prefers_modern_field_if_both_present() {
+        let mut value = json!({
+            "id": "device-mixed",
+            "client_name": "old name",
+            "clientName": "new name",
+        });
+        fixup_legacy_envelope(&mut value);
+        assert_eq!(value["clientName"], "new name");
+    }
+
+    #[test]
+    fn test_stage_incoming_accepts_legacy_dialect_when_opted_in() {
+        env_logger::try_init().ok();
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test_legacy_envelope_compat",
+        )));
+        configure(&engine);
+        engine
+            .store
+            .storage
+            .lock()
+            .unwrap()
+            .set_engine_pref("legacy-envelope-compat", "true")
+            .unwrap();
+
+        let mut telem = telemetry::Engine::new("tabs");
+        let incoming = vec![IncomingBso::from_test_content(legacy_dialect_record())];
+        engine
+            .stage_incoming(incoming, &mut telem)
+            .expect("should stage");
+        engine
+            .apply(ServerTimestamp(0), &mut telem)
+            .expect("should apply");
+
+        let mut storage = engine.store.storage.lock().unwrap();
+        let crts = storage.get_remote_tabs(true).expect("should work");
+        assert_eq!(crts.len(), 1);
+        assert_eq!(crts[0].client_name, "legacy device");
+        assert_eq!(crts[0].remote_tabs.len(), 1);
+        assert_eq!(crts[0].remote_tabs[0].title, "the title");
+    }
+
+    #[test]
+    fn test_stage_incoming_ignores_legacy_dialect_by_default() {
+        env_logger::try_init().ok();
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test_legacy_envelope_compat_off",
+        )));
+        configure(&engine);
+
+        let mut telem = telemetry::Engine::new("tabs");
+        let incoming = vec![IncomingBso::from_test_content(legacy_dialect_record())];
+        engine
+            .stage_incoming(incoming, &mut telem)
+            .expect("should stage");
+        engine
+            .apply(ServerTimestamp(0), &mut telem)
+            .expect("should apply");
+
+        let mut storage = engine.store.storage.lock().unwrap();
+        // `clientName` is required, so without the fixup this record is
+        // malformed and dropped entirely, rather than misparsed.
+        assert!(storage
+            .get_remote_tabs(true)
+            .expect("should work")
+            .is_empty());
+    }
+
     #[test]
     fn test_no_incoming_doesnt_write() {
         env_logger::try_init().ok();
@@ -417,6 +1209,7 @@ pub mod test {
         let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
             "test_no_incoming_doesnt_write",
         )));
+        configure(&engine);
 
         let records = vec![json!({
             "id": "device-with-a-tab",
@@ -446,7 +1239,7 @@ pub mod test {
         // now check the store has what we think it has.
         {
             let mut storage = engine.store.storage.lock().unwrap();
-            assert_eq!(storage.get_remote_tabs().expect("should work").len(), 1);
+            assert_eq!(storage.get_remote_tabs(true).expect("should work").len(), 1);
         }
 
         // Now another sync with zero incoming records, should still be able to get back
@@ -457,69 +1250,893 @@ pub mod test {
 
         {
             let mut storage = engine.store.storage.lock().unwrap();
-            assert_eq!(storage.get_remote_tabs().expect("should work").len(), 1);
+            assert_eq!(storage.get_remote_tabs(true).expect("should work").len(), 1);
         }
     }
 
     #[test]
-    fn test_sync_manager_registration() {
-        let store = Arc::new(TabsStore::new_with_mem_path("test-registration"));
-        assert_eq!(Arc::strong_count(&store), 1);
-        assert_eq!(Arc::weak_count(&store), 0);
-        Arc::clone(&store).register_with_sync_manager();
-        assert_eq!(Arc::strong_count(&store), 1);
-        assert_eq!(Arc::weak_count(&store), 1);
-        let registered = STORE_FOR_MANAGER
-            .lock()
-            .unwrap()
-            .upgrade()
-            .expect("should upgrade");
-        assert!(Arc::ptr_eq(&store, &registered));
-        drop(registered);
-        // should be no new references
-        assert_eq!(Arc::strong_count(&store), 1);
-        assert_eq!(Arc::weak_count(&store), 1);
-        // dropping the registered object should drop the registration.
-        drop(store);
-        assert!(STORE_FOR_MANAGER.lock().unwrap().upgrade().is_none());
-    }
-
-    #[test]
-    fn test_apply_timestamp() {
+    fn test_client_capabilities_surfaced() {
         env_logger::try_init().ok();
 
         let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
-            "test-apply-timestamp",
+            "test-client-capabilities",
         )));
 
+        let client_data = ClientData {
+            local_client_id: "local-device".to_string(),
+            recent_clients: HashMap::from([(
+                "device-with-a-tab".to_string(),
+                RemoteClient {
+                    fxa_device_id: None,
+                    device_name: "device with a tab".to_string(),
+                    device_type: DeviceType::Desktop,
+                    capabilities: vec!["sendTabCommand".to_string()],
+                    os: None,
+                    form_factor: None,
+                },
+            )]),
+        };
+        engine
+            .prepare_for_sync(&|| client_data.clone())
+            .expect("should prepare");
+
         let records = vec![json!({
-            "id": "device-no-tabs",
-            "clientName": "device with no tabs",
+            "id": "device-with-a-tab",
+            "clientName": "device with a tab",
             "tabs": [],
         })];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        engine
+            .stage_incoming(incoming, &mut telemetry::Engine::new("tabs"))
+            .expect("should stage");
 
-        let mut telem = telemetry::Engine::new("tabs");
+        let mut storage = engine.store.storage.lock().unwrap();
+        let crts = storage.get_remote_tabs(true).expect("should work");
+        assert_eq!(crts.len(), 1);
+        assert_eq!(crts[0].capabilities, vec!["sendTabCommand".to_string()]);
+    }
+
+    #[test]
+    fn test_client_os_and_form_factor_surfaced() {
+        env_logger::try_init().ok();
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-client-os-form-factor",
+        )));
+
+        let client_data = ClientData {
+            local_client_id: "local-device".to_string(),
+            recent_clients: HashMap::from([
+                (
+                    "device-with-os".to_string(),
+                    RemoteClient {
+                        fxa_device_id: None,
+                        device_name: "device with os".to_string(),
+                        device_type: DeviceType::Desktop,
+                        capabilities: vec![],
+                        os: Some("Darwin".to_string()),
+                        form_factor: Some("desktop".to_string()),
+                    },
+                ),
+                (
+                    "device-without-os".to_string(),
+                    RemoteClient {
+                        fxa_device_id: None,
+                        device_name: "device without os".to_string(),
+                        device_type: DeviceType::Mobile,
+                        capabilities: vec![],
+                        os: None,
+                        form_factor: None,
+                    },
+                ),
+            ]),
+        };
         engine
-            .set_last_sync(ServerTimestamp::from_millis(123))
-            .unwrap();
+            .prepare_for_sync(&|| client_data.clone())
+            .expect("should prepare");
+
+        let records = vec![
+            json!({"id": "device-with-os", "clientName": "device with os", "tabs": []}),
+            json!({"id": "device-without-os", "clientName": "device without os", "tabs": []}),
+        ];
         let incoming = records
             .into_iter()
             .map(IncomingBso::from_test_content)
             .collect();
         engine
-            .stage_incoming(incoming, &mut telem)
-            .expect("Should apply incoming and stage outgoing records");
+            .stage_incoming(incoming, &mut telemetry::Engine::new("tabs"))
+            .expect("should stage");
+
+        let mut storage = engine.store.storage.lock().unwrap();
+        let mut crts = storage.get_remote_tabs(true).expect("should work");
+        crts.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        assert_eq!(crts[0].os.as_deref(), Some("Darwin"));
+        assert_eq!(crts[0].form_factor.as_deref(), Some("desktop"));
+        assert_eq!(crts[1].os, None);
+        assert_eq!(crts[1].form_factor, None);
+    }
+
+    #[test]
+    fn test_stage_incoming_caps_tabs_per_session() {
+        env_logger::try_init().ok();
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-stage-incoming-session-cap",
+        )));
         engine
-            .apply(ServerTimestamp(0), &mut telem)
-            .expect("should apply");
+            .prepare_for_sync(&|| ClientData {
+                local_client_id: "local-device".to_string(),
+                recent_clients: HashMap::new(),
+            })
+            .expect("should prepare");
+        // Pretend the session cap is tiny so the test doesn't need to build
+        // tens of thousands of records.
+        engine
+            .staged_tabs_this_session
+            .set(MAX_STAGED_TABS_PER_SESSION - 1);
+
+        let records = vec![
+            json!({"id": "device-1", "clientName": "device 1", "tabs": []}),
+            json!({"id": "device-2", "clientName": "device 2", "tabs": []}),
+        ];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        engine
+            .stage_incoming(incoming, &mut telemetry::Engine::new("tabs"))
+            .expect("should stage");
 
+        // Only one of the two records fit under the cap.
+        let mut storage = engine.store.storage.lock().unwrap();
+        assert_eq!(storage.get_remote_tabs(true).expect("should work").len(), 1);
+        assert_eq!(storage.get_stage_cap_violations().unwrap(), 1);
         assert_eq!(
-            engine
-                .get_last_sync()
-                .expect("should work")
-                .expect("should have a value"),
-            ServerTimestamp::from_millis(123),
-            "didn't set a zero timestamp"
+            engine.staged_tabs_this_session.get(),
+            MAX_STAGED_TABS_PER_SESSION
+        );
+
+        // A fresh sync session resets the cap.
+        drop(storage);
+        engine
+            .prepare_for_sync(&|| ClientData {
+                local_client_id: "local-device".to_string(),
+                recent_clients: HashMap::new(),
+            })
+            .expect("should prepare");
+        assert_eq!(engine.staged_tabs_this_session.get(), 0);
+    }
+
+    #[test]
+    fn test_stage_incoming_removes_tombstoned_clients() {
+        env_logger::try_init().ok();
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-stage-incoming-tombstone",
+        )));
+        engine
+            .prepare_for_sync(&|| ClientData {
+                local_client_id: "local-device".to_string(),
+                recent_clients: HashMap::new(),
+            })
+            .expect("should prepare");
+
+        let records = vec![
+            json!({"id": "device-1", "clientName": "device 1", "tabs": []}),
+            json!({"id": "device-2", "clientName": "device 2", "tabs": []}),
+        ];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        engine
+            .stage_incoming(incoming, &mut telemetry::Engine::new("tabs"))
+            .expect("should stage");
+        assert_eq!(
+            engine
+                .store
+                .storage
+                .lock()
+                .unwrap()
+                .get_remote_tabs(true)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // A tombstone for `device-1` arrives on a later sync - it should drop
+        // that client's record rather than being ignored as just another
+        // invalid payload (which would let the stale record linger until
+        // `remove_stale_clients`'s TTL).
+        let tombstone = IncomingBso::new_test_tombstone(Guid::new("device-1"));
+        engine
+            .stage_incoming(vec![tombstone], &mut telemetry::Engine::new("tabs"))
+            .expect("should stage");
+
+        let remote_tabs = engine
+            .store
+            .storage
+            .lock()
+            .unwrap()
+            .get_remote_tabs(true)
+            .unwrap();
+        assert_eq!(remote_tabs.len(), 1);
+        assert_eq!(remote_tabs[0].client_id, "device-2");
+    }
+
+    #[test]
+    fn test_stage_incoming_interrupted() {
+        env_logger::try_init().ok();
+
+        struct InterruptAfter {
+            remaining: std::sync::atomic::AtomicUsize,
+        }
+        impl Interruptee for InterruptAfter {
+            fn was_interrupted(&self) -> bool {
+                use std::sync::atomic::Ordering;
+                if self.remaining.load(Ordering::SeqCst) == 0 {
+                    return true;
+                }
+                self.remaining.fetch_sub(1, Ordering::SeqCst);
+                false
+            }
+        }
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-stage-incoming-interrupted",
+        )));
+        configure(&engine);
+        engine.set_interruptee(Arc::new(InterruptAfter {
+            remaining: std::sync::atomic::AtomicUsize::new(1),
+        }));
+
+        let records = vec![
+            json!({"id": "device-1", "clientName": "one", "tabs": []}),
+            json!({"id": "device-2", "clientName": "two", "tabs": []}),
+            json!({"id": "device-3", "clientName": "three", "tabs": []}),
+        ];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        let mut telem = telemetry::Engine::new("tabs");
+        let err = engine
+            .stage_incoming(incoming, &mut telem)
+            .expect_err("should be interrupted before finishing");
+        assert!(err.to_string().contains("interrupt"));
+    }
+
+    #[test]
+    fn test_stage_incoming_interrupted_while_applying() {
+        env_logger::try_init().ok();
+
+        // Unlike `test_stage_incoming_interrupted`, give the interruptee
+        // enough patience to get through staging every record, so it's the
+        // chunked `replace_remote_tabs_chunked` call afterwards - not the
+        // per-record staging loop - that observes the interruption.
+        struct InterruptAfter {
+            remaining: std::sync::atomic::AtomicUsize,
+        }
+        impl Interruptee for InterruptAfter {
+            fn was_interrupted(&self) -> bool {
+                use std::sync::atomic::Ordering;
+                if self.remaining.load(Ordering::SeqCst) == 0 {
+                    return true;
+                }
+                self.remaining.fetch_sub(1, Ordering::SeqCst);
+                false
+            }
+        }
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-stage-incoming-interrupted-while-applying",
+        )));
+        configure(&engine);
+        let records = vec![
+            json!({"id": "device-1", "clientName": "one", "tabs": []}),
+            json!({"id": "device-2", "clientName": "two", "tabs": []}),
+            json!({"id": "device-3", "clientName": "three", "tabs": []}),
+        ];
+        engine.set_interruptee(Arc::new(InterruptAfter {
+            remaining: std::sync::atomic::AtomicUsize::new(records.len()),
+        }));
+
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        let mut telem = telemetry::Engine::new("tabs");
+        let err = engine
+            .stage_incoming(incoming, &mut telem)
+            .expect_err("should be interrupted before applying");
+        assert!(err.to_string().contains("interrupt"));
+
+        // Nothing was committed - a chunk that never got its interruption
+        // check to pass never opens a transaction.
+        assert!(engine
+            .store
+            .storage
+            .lock()
+            .unwrap()
+            .get_remote_tabs(true)
+            .unwrap_or_default()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_abort_sync_interrupts_in_flight_stage_incoming() {
+        env_logger::try_init().ok();
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-abort-sync-interrupts",
+        )));
+        configure(&engine);
+        engine.abort_sync(AbortSyncReason::Discard);
+
+        let records = vec![json!({"id": "device-1", "clientName": "one", "tabs": []})];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        let err = engine
+            .stage_incoming(incoming, &mut telemetry::Engine::new("tabs"))
+            .expect_err("should be aborted");
+        assert!(matches!(
+            err.downcast::<crate::Error>(),
+            Ok(crate::Error::SyncAborted)
+        ));
+
+        // A fresh sync session clears the abort signal so a later attempt
+        // isn't stuck bailing out forever.
+        engine.on_sync_started().expect("should start");
+        let records = vec![json!({"id": "device-2", "clientName": "two", "tabs": []})];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        engine
+            .stage_incoming(incoming, &mut telemetry::Engine::new("tabs"))
+            .expect("should stage now that the abort signal is cleared");
+    }
+
+    #[test]
+    fn test_abort_sync_reason_controls_staged_tabs_this_session() {
+        assert_staged_tabs_after_abort(AbortSyncReason::Discard, 0);
+        assert_staged_tabs_after_abort(AbortSyncReason::Resume, 5);
+    }
+
+    fn assert_staged_tabs_after_abort(reason: AbortSyncReason, expected: u32) {
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-abort-sync-reason",
+        )));
+        engine.staged_tabs_this_session.set(5);
+        engine.abort_sync(reason);
+        assert_eq!(engine.staged_tabs_this_session.get(), expected);
+    }
+
+    #[test]
+    fn test_reentrant_interruptee_does_not_deadlock() {
+        // Simulates an embedder whose `Interruptee` impl calls straight back into
+        // the engine (eg to install a new interruptee) - this used to deadlock
+        // because `stage_incoming` held a read lock on `interruptee` for the
+        // whole loop, and a reentrant `set_interruptee` needs the write lock.
+        struct ReentrantInterruptee {
+            engine: Weak<TabsEngine>,
+        }
+        impl Interruptee for ReentrantInterruptee {
+            fn was_interrupted(&self) -> bool {
+                if let Some(engine) = self.engine.upgrade() {
+                    engine.set_interruptee(Arc::new(NeverInterrupts));
+                }
+                false
+            }
+        }
+
+        let engine = Arc::new(TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-reentrant-interruptee",
+        ))));
+        configure(&engine);
+        engine.set_interruptee(Arc::new(ReentrantInterruptee {
+            engine: Arc::downgrade(&engine),
+        }));
+
+        let records = vec![
+            json!({"id": "device-1", "clientName": "one", "tabs": []}),
+            json!({"id": "device-2", "clientName": "two", "tabs": []}),
+        ];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        let mut telem = telemetry::Engine::new("tabs");
+        // Should complete cleanly - no panic, no deadlock.
+        engine.stage_incoming(incoming, &mut telem).unwrap();
+    }
+
+    #[test]
+    fn test_sync_observer_notified_of_apply_wipe_and_reset() {
+        struct RecordingObserver {
+            events: Mutex<Vec<String>>,
+        }
+        impl TabsSyncObserver for RecordingObserver {
+            fn on_apply(&self, summary: TabsChangeSummary) {
+                self.events.lock().unwrap().push(format!(
+                    "apply({},{})",
+                    summary.incoming_tabs, summary.outgoing_tabs
+                ));
+            }
+            fn on_wipe(&self) {
+                self.events.lock().unwrap().push("wipe".to_string());
+            }
+            fn on_reset(&self) {
+                self.events.lock().unwrap().push("reset".to_string());
+            }
+        }
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test-sync-observer"));
+        let observer = Arc::new(RecordingObserver {
+            events: Mutex::new(Vec::new()),
+        });
+        store.set_sync_observer(observer.clone());
+        let engine = TabsEngine::new(store);
+        configure(&engine);
+
+        let records = vec![json!({"id": "device-1", "clientName": "one", "tabs": []})];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        let mut telem = telemetry::Engine::new("tabs");
+        engine.stage_incoming(incoming, &mut telem).unwrap();
+        engine.apply(ServerTimestamp(0), &mut telem).unwrap();
+
+        engine.wipe().unwrap();
+
+        assert_eq!(
+            *observer.events.lock().unwrap(),
+            vec![
+                "apply(1,0)".to_string(),
+                "reset".to_string(),
+                "wipe".to_string()
+            ],
+        );
+    }
+
+    #[test]
+    fn test_sync_observer_apply_summary_lists_changed_client_ids() {
+        struct RecordingObserver {
+            summaries: Mutex<Vec<TabsChangeSummary>>,
+        }
+        impl TabsSyncObserver for RecordingObserver {
+            fn on_apply(&self, summary: TabsChangeSummary) {
+                self.summaries.lock().unwrap().push(summary);
+            }
+            fn on_wipe(&self) {}
+            fn on_reset(&self) {}
+        }
+
+        let store = Arc::new(TabsStore::new_with_mem_path(
+            "test-sync-observer-changed-client-ids",
+        ));
+        let observer = Arc::new(RecordingObserver {
+            summaries: Mutex::new(Vec::new()),
+        });
+        store.set_sync_observer(observer.clone());
+        let engine = TabsEngine::new(store);
+        configure(&engine);
+
+        let records = vec![
+            json!({"id": "device-1", "clientName": "one", "tabs": []}),
+            json!({"id": "device-2", "clientName": "two", "tabs": []}),
+        ];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        let mut telem = telemetry::Engine::new("tabs");
+        engine.stage_incoming(incoming, &mut telem).unwrap();
+        engine.apply(ServerTimestamp(0), &mut telem).unwrap();
+
+        let summaries = observer.summaries.lock().unwrap();
+        assert_eq!(summaries.len(), 1);
+        let mut changed = summaries[0].changed_client_ids.clone();
+        changed.sort();
+        assert_eq!(
+            changed,
+            vec!["device-1".to_string(), "device-2".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_stage_incoming_refreshes_the_cached_remote_tabs_fast_path() {
+        let store = Arc::new(TabsStore::new_with_mem_path(
+            "test-cached-remote-tabs-fast-path",
+        ));
+        let engine = TabsEngine::new(store.clone());
+        configure(&engine);
+
+        assert_eq!(store.get_cached_remote_tabs_json(), None);
+
+        let records = vec![json!({"id": "device-1", "clientName": "one", "tabs": []})];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        let mut telem = telemetry::Engine::new("tabs");
+        engine.stage_incoming(incoming, &mut telem).unwrap();
+
+        let cached = store
+            .get_cached_remote_tabs_json()
+            .expect("stage_incoming should have populated the cache");
+        let cached: Vec<serde_json::Value> = serde_json::from_str(&cached).unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0]["client_id"], "device-1");
+
+        engine.wipe().unwrap();
+        assert_eq!(store.get_cached_remote_tabs_json(), None);
+    }
+
+    #[test]
+    fn test_wipe_clears_the_remote_mirror() {
+        let store = Arc::new(TabsStore::new_with_mem_path("test-wipe-remote-mirror"));
+        let engine = TabsEngine::new(store.clone());
+        configure(&engine);
+
+        let records = vec![json!({"id": "device-1", "clientName": "one", "tabs": []})];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        let mut telem = telemetry::Engine::new("tabs");
+        engine.stage_incoming(incoming, &mut telem).unwrap();
+        engine.apply(ServerTimestamp(0), &mut telem).unwrap();
+        assert_eq!(store.remote_tabs(true).unwrap().len(), 1);
+
+        engine.wipe().unwrap();
+
+        assert_eq!(store.remote_tabs(true), Some(vec![]));
+    }
+
+    #[test]
+    fn test_sync_observer_invalidate_fires_with_reason() {
+        struct RecordingObserver {
+            reasons: Mutex<Vec<InvalidateReason>>,
+        }
+        impl TabsSyncObserver for RecordingObserver {
+            fn on_apply(&self, _summary: TabsChangeSummary) {}
+            fn on_invalidate(&self, reason: InvalidateReason) {
+                self.reasons.lock().unwrap().push(reason);
+            }
+            fn on_wipe(&self) {}
+            fn on_reset(&self) {}
+        }
+
+        let store = Arc::new(TabsStore::new_with_mem_path(
+            "test-sync-observer-invalidate",
+        ));
+        let observer = Arc::new(RecordingObserver {
+            reasons: Mutex::new(Vec::new()),
+        });
+        store.set_sync_observer(observer.clone());
+        let engine = TabsEngine::new(store);
+
+        engine.reset(&EngineSyncAssociation::Disconnected).unwrap();
+        engine.wipe().unwrap();
+
+        assert_eq!(
+            *observer.reasons.lock().unwrap(),
+            vec![
+                InvalidateReason::Reset,
+                // `wipe()` resets first, then wipes.
+                InvalidateReason::Reset,
+                InvalidateReason::Wipe,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_upload_policy_denies_outgoing_but_still_processes_incoming() {
+        struct DenyUploads;
+        impl crate::policy::UploadPolicyCheck for DenyUploads {
+            fn should_upload(&self) -> bool {
+                false
+            }
+        }
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test-upload-policy"));
+        store.set_upload_policy_check(Arc::new(DenyUploads));
+        let engine = TabsEngine::new(store.clone());
+        configure(&engine);
+        store.set_local_tabs(vec![RemoteTab {
+            title: "my tab".to_string(),
+            url_history: vec!["https://mozilla.org/".to_string()],
+            icon: None,
+            last_used: 1643764207,
+            inactive: false,
+            last_modified: None,
+        }]);
+
+        let records = vec![json!({"id": "device-1", "clientName": "one", "tabs": []})];
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        let mut telem = telemetry::Engine::new("tabs");
+        engine.stage_incoming(incoming, &mut telem).unwrap();
+        let outgoing = engine.apply(ServerTimestamp(0), &mut telem).unwrap();
+
+        // Incoming was still processed...
+        let mut storage = engine.store.storage.lock().unwrap();
+        assert_eq!(storage.get_remote_tabs(true).unwrap().len(), 1);
+        drop(storage);
+
+        // ...but the policy blocked the upload, and the local tabs are still
+        // there, ready to be uploaded next time the policy allows it.
+        assert!(outgoing.is_empty());
+        assert!(engine
+            .store
+            .storage
+            .lock()
+            .unwrap()
+            .prepare_local_tabs_for_upload()
+            .is_some());
+    }
+
+    #[test]
+    fn test_incoming_tab_length_caps_enforced() {
+        env_logger::try_init().ok();
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-incoming-length-caps",
+        )));
+        configure(&engine);
+
+        let long_title = "を".repeat(600); // over MAX_TITLE_CHAR_LENGTH, multi-byte
+        let long_url = format!("https://example.com/{}", "a".repeat(70_000));
+        let records = vec![json!({
+            "id": "device-with-huge-tab",
+            "clientName": "device with a huge tab",
+            "tabs": [{
+                "title": long_title,
+                "urlHistory": [long_url, "https://mozilla.org/"],
+                "lastUsed": 1643764207
+            }]
+        })];
+
+        let mut telem = telemetry::Engine::new("tabs");
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        engine
+            .stage_incoming(incoming, &mut telem)
+            .expect("Should apply incoming and stage outgoing records");
+
+        let mut storage = engine.store.storage.lock().unwrap();
+        let crts = storage.get_remote_tabs(true).expect("should work");
+        assert_eq!(crts.len(), 1);
+        let tab = &crts[0].remote_tabs[0];
+        assert!(tab.title.chars().count() <= crate::storage::MAX_TITLE_CHAR_LENGTH);
+        assert_eq!(tab.url_history, vec!["https://mozilla.org/".to_string()]);
+        assert_eq!(storage.get_length_cap_violations().expect("should work"), 1);
+    }
+
+    #[test]
+    fn test_sync_manager_registration() {
+        let store = Arc::new(TabsStore::new_with_mem_path("test-registration"));
+        assert_eq!(Arc::strong_count(&store), 1);
+        assert_eq!(Arc::weak_count(&store), 0);
+        Arc::clone(&store).register_with_sync_manager();
+        assert_eq!(Arc::strong_count(&store), 1);
+        assert_eq!(Arc::weak_count(&store), 1);
+        let registered = STORE_FOR_MANAGER
+            .lock()
+            .unwrap()
+            .upgrade()
+            .expect("should upgrade");
+        assert!(Arc::ptr_eq(&store, &registered));
+        drop(registered);
+        // should be no new references
+        assert_eq!(Arc::strong_count(&store), 1);
+        assert_eq!(Arc::weak_count(&store), 1);
+        // dropping the registered object should drop the registration.
+        drop(store);
+        assert!(STORE_FOR_MANAGER.lock().unwrap().upgrade().is_none());
+    }
+
+    #[test]
+    fn test_apply_timestamp() {
+        env_logger::try_init().ok();
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-apply-timestamp",
+        )));
+        configure(&engine);
+
+        let records = vec![json!({
+            "id": "device-no-tabs",
+            "clientName": "device with no tabs",
+            "tabs": [],
+        })];
+
+        let mut telem = telemetry::Engine::new("tabs");
+        engine
+            .set_last_sync(ServerTimestamp::from_millis(123))
+            .unwrap();
+        let incoming = records
+            .into_iter()
+            .map(IncomingBso::from_test_content)
+            .collect();
+        engine
+            .stage_incoming(incoming, &mut telem)
+            .expect("Should apply incoming and stage outgoing records");
+        engine
+            .apply(ServerTimestamp(0), &mut telem)
+            .expect("should apply");
+
+        assert_eq!(
+            engine
+                .get_last_sync()
+                .expect("should work")
+                .expect("should have a value"),
+            ServerTimestamp::from_millis(123),
+            "didn't set a zero timestamp"
         )
     }
+
+    #[test]
+    fn test_set_last_sync_small_regression_clamps_without_forcing_refresh() {
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-last-sync-small-regression",
+        )));
+
+        engine
+            .set_last_sync(ServerTimestamp::from_millis(1_000_000))
+            .unwrap();
+        // A small step backwards - clock skew, not a server-side reset.
+        engine
+            .set_last_sync(ServerTimestamp::from_millis(999_000))
+            .unwrap();
+
+        assert_eq!(
+            engine.get_last_sync().unwrap().unwrap(),
+            ServerTimestamp::from_millis(1_000_000),
+            "should have clamped to the previous value instead of regressing"
+        );
+        let request = engine
+            .get_collection_request(ServerTimestamp::from_millis(1_000_000))
+            .unwrap();
+        assert!(
+            request.is_none(),
+            "since our clamped last_sync matches the server timestamp, there's nothing to fetch"
+        );
+    }
+
+    #[test]
+    fn test_set_last_sync_large_regression_forces_full_refresh() {
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-last-sync-large-regression",
+        )));
+
+        engine
+            .set_last_sync(ServerTimestamp::from_millis(
+                2 * TIMESTAMP_REGRESSION_REFRESH_THRESHOLD_MS,
+            ))
+            .unwrap();
+        // A jump back bigger than the threshold - treat it as a possible
+        // server-side reset rather than ordinary clock skew.
+        engine
+            .set_last_sync(ServerTimestamp::from_millis(0))
+            .unwrap();
+
+        assert_eq!(
+            engine.get_last_sync().unwrap().unwrap(),
+            ServerTimestamp::from_millis(2 * TIMESTAMP_REGRESSION_REFRESH_THRESHOLD_MS),
+            "should still clamp rather than storing the regressed value"
+        );
+
+        let request = engine
+            .get_collection_request(ServerTimestamp::from_millis(
+                2 * TIMESTAMP_REGRESSION_REFRESH_THRESHOLD_MS,
+            ))
+            .unwrap()
+            .expect("should request a full refresh even though the server timestamp matches");
+        assert_eq!(request.newer, Some(ServerTimestamp(0)));
+
+        // The flag is one-shot - the next request goes back to normal incremental sync.
+        let request = engine
+            .get_collection_request(ServerTimestamp::from_millis(
+                2 * TIMESTAMP_REGRESSION_REFRESH_THRESHOLD_MS,
+            ))
+            .unwrap();
+        assert!(request.is_none());
+    }
+
+    #[test]
+    fn test_metrics_reporting_dispatches_snapshots_until_stopped() {
+        use crate::metrics::MetricsReportingCallback;
+        use std::sync::mpsc;
+
+        struct Callback(mpsc::Sender<String>);
+        impl MetricsReportingCallback for Callback {
+            fn on_metrics_snapshot(&self, snapshot_json: String) {
+                let _ = self.0.send(snapshot_json);
+            }
+        }
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test-metrics-reporting"));
+        let (tx, rx) = mpsc::channel();
+        store
+            .clone()
+            .start_metrics_reporting(10, Box::new(Callback(tx)));
+
+        let snapshot = rx
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .expect("should have received at least one snapshot");
+        let parsed: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+        assert!(parsed.get("db_size_bytes").is_some());
+        assert!(parsed.get("length_cap_violations").is_some());
+
+        store.stop_metrics_reporting();
+        // Drain anything already in flight, then make sure nothing more shows up.
+        while rx
+            .recv_timeout(std::time::Duration::from_millis(50))
+            .is_ok()
+        {}
+        assert!(rx
+            .recv_timeout(std::time::Duration::from_millis(100))
+            .is_err());
+    }
+
+    #[test]
+    fn test_saturating_u32_caps_instead_of_wrapping() {
+        assert_eq!(saturating_u32(0), 0);
+        assert_eq!(saturating_u32(42), 42);
+        assert_eq!(saturating_u32(usize::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn test_set_last_sync_handles_extreme_timestamps() {
+        // Exercises the boundary values a 32-bit build could plausibly disagree
+        // with a 64-bit one about, plus the max/min `i64` a malformed server
+        // response could send.
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test-last-sync-extreme-timestamps",
+        )));
+
+        engine
+            .set_last_sync(ServerTimestamp::from_millis(i64::from(u32::MAX)))
+            .unwrap();
+        assert_eq!(
+            engine.get_last_sync().unwrap().unwrap(),
+            ServerTimestamp::from_millis(i64::from(u32::MAX))
+        );
+
+        engine
+            .set_last_sync(ServerTimestamp::from_millis(i64::MAX))
+            .unwrap();
+        assert_eq!(
+            engine.get_last_sync().unwrap().unwrap(),
+            ServerTimestamp::from_millis(i64::MAX)
+        );
+
+        // A regression all the way down to `i64::MIN` should clamp (not panic
+        // on overflow computing `regressed_by`) and force a refresh.
+        engine
+            .set_last_sync(ServerTimestamp::from_millis(i64::MIN))
+            .unwrap();
+        assert_eq!(
+            engine.get_last_sync().unwrap().unwrap(),
+            ServerTimestamp::from_millis(i64::MAX),
+            "should have clamped rather than regressing"
+        );
+        let request = engine
+            .get_collection_request(ServerTimestamp::from_millis(i64::MAX))
+            .unwrap()
+            .expect("large regression should force a full refresh");
+        assert_eq!(request.newer, Some(ServerTimestamp(0)));
+    }
 }
@@ -2,6 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+// The bridged engine and the sync-manager-registered engine are both
+// gated behind `full-sync` - see that feature's doc comment in Cargo.toml.
+// `record` stays unconditional: it's the data model the local mirror itself
+// is encoded with (see `storage::encode_record`), not sync-specific.
+#[cfg(feature = "full-sync")]
 pub(crate) mod bridge;
+#[cfg(feature = "full-sync")]
 pub(crate) mod engine;
 pub(crate) mod record;
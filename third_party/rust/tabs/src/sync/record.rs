@@ -18,17 +18,57 @@ pub struct TabsRecordTab {
     pub last_used: i64, // Seconds since epoch!
     #[serde(default, skip_serializing_if = "skip_if_default")]
     pub inactive: bool,
+    // Some (mostly mobile) clients send a modification time for the tab itself,
+    // distinct from `last_used` - absent from clients (or old records) that
+    // don't send it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<i64>, // Seconds since epoch!
 }
 
+// An acknowledgement of a command (eg a remote tab-close request) targeted at
+// this record's sender - see `crate::storage::TabsStorage::queue_command_ack`.
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct CommandAck {
+    pub command_id: String,
+    pub status: String,
+    pub timestamp: i64, // Seconds since epoch, same convention as `TabsRecordTab::last_used`.
+}
+
+// A "close this tab" request this record's sender is issuing to
+// `target_client_id` - see `crate::storage::TabsStorage::queue_close_remote_tab_command`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TabCommand {
+    pub command_id: String,
+    pub target_client_id: String,
+    pub url: String,
+    pub created_at: i64, // Seconds since epoch, same convention as `CommandAck::timestamp`.
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
 // This struct mirrors what is stored on the server
 pub struct TabsRecord {
     // `String` instead of `SyncGuid` because some IDs are FxA device ID (XXX - that doesn't
     // matter though - this could easily be a Guid!)
     pub id: String,
     pub client_name: String,
+    // Some clients omit `tabs` entirely for a device with zero tabs, rather than
+    // sending an empty array - treat the two the same.
+    #[serde(default)]
     pub tabs: Vec<TabsRecordTab>,
+    // Acks this client owes for commands it's processed, queued via
+    // `TabsStorage::queue_command_ack` - absent from clients that don't send
+    // any, and from every older client that doesn't know about them at all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acks: Vec<CommandAck>,
+    // Commands this client is issuing, queued via
+    // `TabsStorage::queue_close_remote_tab_command` - absent from clients
+    // that don't send any, and from every older client that doesn't know
+    // about them at all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<TabCommand>,
 }
 
 #[cfg(test)]
@@ -59,6 +99,26 @@ pub mod test {
         assert_eq!(tab.icon, Some("https://mozilla.org/icon".to_string()));
         assert_eq!(tab.last_used, 1643764207);
         assert!(!tab.inactive);
+        assert_eq!(tab.last_modified, None);
+    }
+
+    #[test]
+    fn test_payload_with_last_modified() {
+        let payload = json!({
+            "id": "JkeBPC50ZI0m",
+            "clientName": "client name",
+            "tabs": [{
+                "title": "the title",
+                "urlHistory": [
+                    "https://mozilla.org/"
+                ],
+                "icon": "https://mozilla.org/icon",
+                "lastUsed": 1643764207,
+                "lastModified": 1643764300
+            }]
+        });
+        let record: TabsRecord = serde_json::from_value(payload).expect("should work");
+        assert_eq!(record.tabs[0].last_modified, Some(1643764300));
     }
 
     #[test]
@@ -72,6 +132,18 @@ pub mod test {
                 icon: Some("https://mozilla.org/icon".into()),
                 last_used: 1643764207,
                 inactive: true,
+                last_modified: Some(1643764300),
+            }],
+            acks: vec![CommandAck {
+                command_id: "command-1".into(),
+                status: "done".into(),
+                timestamp: 1643764207,
+            }],
+            commands: vec![TabCommand {
+                command_id: "command-2".into(),
+                target_client_id: "other-client".into(),
+                url: "https://mozilla.org/".into(),
+                created_at: 1643764207,
             }],
         };
         let round_tripped =
@@ -79,6 +151,74 @@ pub mod test {
         assert_eq!(tab, round_tripped);
     }
 
+    #[test]
+    fn test_missing_tabs_field() {
+        // A device with zero tabs that omits `tabs` entirely, rather than sending [].
+        let payload = json!({
+            "id": "JkeBPC50ZI0m",
+            "clientName": "client name",
+        });
+        let record: TabsRecord = serde_json::from_value(payload).expect("should work");
+        assert!(record.tabs.is_empty());
+        assert!(record.acks.is_empty());
+        assert!(record.commands.is_empty());
+    }
+
+    #[test]
+    fn test_acks() {
+        let payload = json!({
+            "id": "JkeBPC50ZI0m",
+            "clientName": "client name",
+            "acks": [{
+                "commandId": "command-1",
+                "status": "done",
+                "timestamp": 1643764207,
+            }]
+        });
+        let record: TabsRecord = serde_json::from_value(payload).expect("should work");
+        assert_eq!(record.acks.len(), 1);
+        assert_eq!(record.acks[0].command_id, "command-1");
+        assert_eq!(record.acks[0].status, "done");
+        // Absent `acks` is never written back out.
+        let no_acks = TabsRecord {
+            id: "JkeBPC50ZI0m".into(),
+            client_name: "client name".into(),
+            tabs: vec![],
+            acks: vec![],
+            commands: vec![],
+        };
+        let value = serde_json::to_value(no_acks).unwrap();
+        assert!(value.get("acks").is_none());
+    }
+
+    #[test]
+    fn test_commands() {
+        let payload = json!({
+            "id": "JkeBPC50ZI0m",
+            "clientName": "client name",
+            "commands": [{
+                "commandId": "command-1",
+                "targetClientId": "other-client",
+                "url": "https://mozilla.org/",
+                "createdAt": 1643764207,
+            }]
+        });
+        let record: TabsRecord = serde_json::from_value(payload).expect("should work");
+        assert_eq!(record.commands.len(), 1);
+        assert_eq!(record.commands[0].command_id, "command-1");
+        assert_eq!(record.commands[0].target_client_id, "other-client");
+        // Absent `commands` is never written back out.
+        let no_commands = TabsRecord {
+            id: "JkeBPC50ZI0m".into(),
+            client_name: "client name".into(),
+            tabs: vec![],
+            acks: vec![],
+            commands: vec![],
+        };
+        let value = serde_json::to_value(no_commands).unwrap();
+        assert!(value.get("commands").is_none());
+    }
+
     #[test]
     fn test_extra_fields() {
         let payload = json!({
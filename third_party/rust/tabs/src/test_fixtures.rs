@@ -0,0 +1,176 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Deterministic fixtures for xpcshell suites exercising this crate's bridge
+//! APIs, so each new method doesn't need its own hand-written setup dance -
+//! see `TabsStore::load_test_fixture`.
+//!
+//! Gated behind the `test-support` feature. Unlike `debug_tools`, this *is*
+//! exposed via uniffi - see the `TEST-SUPPORT-ONLY` section of tabs.udl -
+//! since xpcshell suites call into it from JS rather than from a Rust test
+//! harness. Never enabled in a release build.
+//!
+//! Each fixture is a fixed, named scenario - canned incoming records, a
+//! fixed clock, or a fault to inject - rather than a builder a suite
+//! assembles piece by piece, so the same fixture name means the same thing
+//! in every suite that loads it.
+
+use crate::error::{Error, Result};
+use crate::storage::TabsStorage;
+use crate::sync::record::{TabsRecord, TabsRecordTab};
+use sync15::ServerTimestamp;
+
+/// Implemented by the embedder (an xpcshell test helper, in practice) to be
+/// notified once a `TabsStore::load_test_fixture` call has applied its
+/// fixture. Called from the caller's own thread, synchronously - fixtures
+/// are cheap enough that a background thread (like `export`/`import`'s)
+/// would only add latency. An unknown fixture name (or a failure applying
+/// it) is reported by `load_test_fixture` returning `Err` rather than
+/// through this callback.
+pub trait TestFixtureCallback: Send + Sync {
+    fn on_fixture_loaded(&self);
+}
+
+// A fixed point in time fixtures use instead of the real clock, so
+// assertions on eg tab-pickup timestamps don't depend on when the suite
+// happened to run.
+const FIXTURE_TIMESTAMP_MILLIS: i64 = 1_600_000_000_000;
+
+/// Applies `name`'s fixture to `storage` and, on success, notifies
+/// `callback` - see `TabsStore::load_test_fixture`.
+pub(crate) fn load(
+    storage: &mut TabsStorage,
+    name: &str,
+    callback: &dyn TestFixtureCallback,
+) -> Result<()> {
+    apply(storage, name)?;
+    callback.on_fixture_loaded();
+    Ok(())
+}
+
+fn apply(storage: &mut TabsStorage, name: &str) -> Result<()> {
+    match name {
+        "single-client-one-tab" => apply_single_client_one_tab(storage),
+        "two-clients" => apply_two_clients(storage),
+        "fault-disk-full" => apply_fault_disk_full(storage),
+        other => Err(Error::UnknownTestFixture(other.to_string())),
+    }
+}
+
+fn fixture_record(client_id: &str, client_name: &str, title: &str, url: &str) -> TabsRecord {
+    TabsRecord {
+        id: client_id.to_string(),
+        client_name: client_name.to_string(),
+        tabs: vec![TabsRecordTab {
+            title: title.to_string(),
+            url_history: vec![url.to_string()],
+            last_used: FIXTURE_TIMESTAMP_MILLIS,
+            ..Default::default()
+        }],
+        acks: vec![],
+        commands: vec![],
+    }
+}
+
+// A single client with a single tab, landed at `FIXTURE_TIMESTAMP_MILLIS` -
+// the smallest fixture that exercises `get_all`/`get_for_display`/
+// `filter_remote_tabs` without pulling in multi-client concerns.
+fn apply_single_client_one_tab(storage: &mut TabsStorage) -> Result<()> {
+    storage.replace_remote_tabs(vec![(
+        fixture_record(
+            "device-1",
+            "Desktop",
+            "Example Domain",
+            "https://example.com/",
+        ),
+        ServerTimestamp::from_millis(FIXTURE_TIMESTAMP_MILLIS),
+    )])
+}
+
+// Two clients, each with one tab, for suites exercising multi-client
+// rendering (`get_for_display`'s per-client sorting, `get_devices_with_url`)
+// without needing to assemble the records by hand.
+fn apply_two_clients(storage: &mut TabsStorage) -> Result<()> {
+    storage.replace_remote_tabs(vec![
+        (
+            fixture_record(
+                "device-1",
+                "Desktop",
+                "Example Domain",
+                "https://example.com/",
+            ),
+            ServerTimestamp::from_millis(FIXTURE_TIMESTAMP_MILLIS),
+        ),
+        (
+            fixture_record("device-2", "Mobile", "Mozilla", "https://mozilla.org/"),
+            ServerTimestamp::from_millis(FIXTURE_TIMESTAMP_MILLIS),
+        ),
+    ])
+}
+
+// Primes the same disk-full backoff window a real SQLITE_FULL would - see
+// `TabsStorage::note_disk_full` - for suites asserting on
+// `TabsApiError::DiskFullError` without needing to actually fill a disk.
+fn apply_fault_disk_full(storage: &mut TabsStorage) -> Result<()> {
+    storage.note_disk_full()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingCallback {
+        loaded: std::sync::atomic::AtomicBool,
+    }
+
+    impl TestFixtureCallback for RecordingCallback {
+        fn on_fixture_loaded(&self) {
+            self.loaded.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_unknown_fixture_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = TabsStorage::new(dir.path().join("test_fixture_unknown.db"));
+        let callback = RecordingCallback::default();
+
+        let err = load(&mut storage, "no-such-fixture", &callback).unwrap_err();
+
+        assert!(matches!(err, Error::UnknownTestFixture(name) if name == "no-such-fixture"));
+        assert!(!callback.loaded.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_single_client_one_tab_lands_in_remote_tabs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = TabsStorage::new(dir.path().join("test_fixture_single_client.db"));
+        let callback = RecordingCallback::default();
+
+        load(&mut storage, "single-client-one-tab", &callback).unwrap();
+
+        assert!(callback.loaded.load(std::sync::atomic::Ordering::SeqCst));
+        let remote_tabs = storage.get_remote_tabs(true).unwrap();
+        assert_eq!(remote_tabs.len(), 1);
+        assert_eq!(remote_tabs[0].client_id, "device-1");
+    }
+
+    #[test]
+    fn test_fault_disk_full_activates_write_backoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = TabsStorage::new(dir.path().join("test_fixture_disk_full.db"));
+        let callback = RecordingCallback::default();
+
+        load(&mut storage, "fault-disk-full", &callback).unwrap();
+
+        let err = storage
+            .replace_remote_tabs(vec![(
+                fixture_record("device-1", "Desktop", "Example", "https://example.com/"),
+                ServerTimestamp::from_millis(FIXTURE_TIMESTAMP_MILLIS),
+            )])
+            .unwrap_err();
+        assert!(matches!(err, Error::DiskFull));
+    }
+}
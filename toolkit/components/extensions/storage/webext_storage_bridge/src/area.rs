@@ -12,7 +12,7 @@ use std::{
     sync::Arc,
 };
 
-use golden_gate::{ApplyTask, BridgedEngine, FerryTask};
+use golden_gate::{ApplyTask, BridgedEngine, FerryTask, SyncHistory, SyncSession};
 use moz_task::{self, DispatchOptions, TaskRunnable};
 use nserror::{nsresult, NS_OK};
 use nsstring::{nsACString, nsCString, nsString};
@@ -57,7 +57,21 @@ fn path_from_nsifile(file: &nsIFile) -> Result<PathBuf> {
 /// implements the interfaces needed for syncing and storage.
 ///
 /// This class can be created on any thread, but must not be shared between
-/// threads. In Rust terms, it's `Send`, but not `Sync`.
+/// threads. In Rust terms, it's `Send`, but not `Sync` - `nonatomic` below
+/// reflects that: a plain, non-atomic refcount is fine because only the
+/// single owning thread ever touches it.
+///
+/// That's true even though `self.queue`'s tasks run on a background thread
+/// pool and call back into this class: `FerryTask`/`PuntTask`/`ApplyTask`
+/// bind their `mozI...Callback` via `moz_task::ThreadPtrHandle`, which hops
+/// the actual callback invocation (`Task::done`) back onto this object's
+/// owning thread instead of calling it from the background thread - see
+/// `golden_gate::task`. And `teardown` itself stays exactly-once without
+/// needing `Sync` either: it `mem::take`s `self.store`, so a second call
+/// finds `None` and returns `Error::AlreadyTornDown` rather than racing the
+/// first. So there's no path where two threads are ever live inside a
+/// `StorageSyncArea` at once, and its interior `RefCell`s don't need to
+/// become `Mutex`es to stay sound.
 #[xpcom(
     implement(
         mozIExtensionStorageArea,
@@ -76,6 +90,15 @@ pub struct StorageSyncArea {
     /// The store is lazily initialized on the task queue the first time it's
     /// used.
     store: RefCell<Option<Arc<LazyStore>>>,
+    /// Accumulates what happens across the ferries of the sync currently in
+    /// progress, if any - replaced with a fresh one in `sync_started`, and
+    /// read (but not replaced) by the `store_incoming`, `apply`,
+    /// `set_uploaded` and `sync_finished` tasks. See
+    /// `golden_gate::SyncSession`.
+    session: RefCell<Arc<SyncSession>>,
+    /// Recently-finished sync summaries, for `getRecentSyncHistory`. Unlike
+    /// `session`, this persists for the lifetime of the area.
+    history: Arc<SyncHistory>,
 }
 
 /// `mozIExtensionStorageArea` implementation.
@@ -86,6 +109,8 @@ impl StorageSyncArea {
         Ok(StorageSyncArea::allocate(InitStorageSyncArea {
             queue,
             store: RefCell::new(Some(Arc::default())),
+            session: RefCell::new(Arc::new(SyncSession::new())),
+            history: Arc::default(),
         }))
     }
 
@@ -341,7 +366,8 @@ impl StorageSyncArea {
         )
     );
     fn get_last_sync(&self, callback: &mozIBridgedSyncEngineCallback) -> Result<()> {
-        Ok(FerryTask::for_last_sync(self.new_bridge()?, callback)?.dispatch(&self.queue)?)
+        // Read-only, so it doesn't need to queue behind writes on `self.queue`.
+        Ok(FerryTask::for_last_sync(self.new_bridge()?, callback)?.dispatch_background()?)
     }
 
     xpcom_method!(
@@ -367,7 +393,8 @@ impl StorageSyncArea {
         )
     );
     fn get_sync_id(&self, callback: &mozIBridgedSyncEngineCallback) -> Result<()> {
-        Ok(FerryTask::for_sync_id(self.new_bridge()?, callback)?.dispatch(&self.queue)?)
+        // Read-only, so it doesn't need to queue behind writes on `self.queue`.
+        Ok(FerryTask::for_sync_id(self.new_bridge()?, callback)?.dispatch_background()?)
     }
 
     xpcom_method!(
@@ -402,7 +429,12 @@ impl StorageSyncArea {
         )
     );
     fn sync_started(&self, callback: &mozIBridgedSyncEngineCallback) -> Result<()> {
-        Ok(FerryTask::for_sync_started(self.new_bridge()?, callback)?.dispatch(&self.queue)?)
+        let session = Arc::new(SyncSession::new());
+        *self.session.borrow_mut() = session.clone();
+        Ok(
+            FerryTask::for_sync_started(self.new_bridge()?, session, callback)?
+                .dispatch(&self.queue)?,
+        )
     }
 
     xpcom_method!(
@@ -419,6 +451,7 @@ impl StorageSyncArea {
         Ok(FerryTask::for_store_incoming(
             self.new_bridge()?,
             incoming_envelopes_json.map(|v| v.as_slice()).unwrap_or(&[]),
+            self.session.borrow().clone(),
             callback,
         )?
         .dispatch(&self.queue)?)
@@ -426,7 +459,12 @@ impl StorageSyncArea {
 
     xpcom_method!(apply => Apply(callback: *const mozIBridgedSyncEngineApplyCallback));
     fn apply(&self, callback: &mozIBridgedSyncEngineApplyCallback) -> Result<()> {
-        Ok(ApplyTask::new(self.new_bridge()?, callback)?.dispatch(&self.queue)?)
+        Ok(ApplyTask::new(
+            self.new_bridge()?,
+            Some(self.session.borrow().clone()),
+            callback,
+        )?
+        .dispatch(&self.queue)?)
     }
 
     xpcom_method!(
@@ -446,6 +484,7 @@ impl StorageSyncArea {
             self.new_bridge()?,
             server_modified_millis,
             uploaded_ids.map(|v| v.as_slice()).unwrap_or(&[]),
+            self.session.borrow().clone(),
             callback,
         )?
         .dispatch(&self.queue)?)
@@ -457,7 +496,29 @@ impl StorageSyncArea {
         )
     );
     fn sync_finished(&self, callback: &mozIBridgedSyncEngineCallback) -> Result<()> {
-        Ok(FerryTask::for_sync_finished(self.new_bridge()?, callback)?.dispatch(&self.queue)?)
+        Ok(FerryTask::for_sync_finished(
+            self.new_bridge()?,
+            self.session.borrow().clone(),
+            self.history.clone(),
+            callback,
+        )?
+        .dispatch(&self.queue)?)
+    }
+
+    xpcom_method!(
+        get_recent_sync_history => GetRecentSyncHistory(
+            callback: *const mozIBridgedSyncEngineCallback
+        )
+    );
+    /// Returns a JSON array of recent sync summaries, for `about:sync` to
+    /// explain "why was the last sync slow" without replaying every
+    /// callback of every sync itself.
+    fn get_recent_sync_history(&self, callback: &mozIBridgedSyncEngineCallback) -> Result<()> {
+        // Read-only, so it doesn't need to queue behind writes on `self.queue`.
+        Ok(
+            FerryTask::for_recent_sync_history(self.new_bridge()?, self.history.clone(), callback)?
+                .dispatch_background()?,
+        )
     }
 
     xpcom_method!(